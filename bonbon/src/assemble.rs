@@ -5,7 +5,10 @@ use {
         state::Collection as MplCollection,
         state::Creator as MplCreator,
     },
-    solana_sdk::{instruction::CompiledInstruction, program_option::COption, pubkey::Pubkey},
+    solana_sdk::{
+        instruction::CompiledInstruction, program_option::COption, pubkey::Pubkey,
+        signature::Signature,
+    },
     spl_token_2022::instruction::{AuthorityType, TokenInstruction},
     std::collections::HashMap,
 };
@@ -117,6 +120,81 @@ pub struct InstructionIndex {
     pub inner_index: Option<i64>,
 }
 
+// SLOT.BLOCK_INDEX.OUTER_INDEX[.INNER_INDEX]
+impl std::fmt::Display for InstructionIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.slot, self.block_index, self.outer_index)?;
+        if let Some(inner_index) = self.inner_index {
+            write!(f, ".{}", inner_index)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for InstructionIndex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('.')
+            .map(|p| p.bytes().all(|b| b.is_ascii_digit()).then(|| p.parse().ok()).flatten())
+            .collect::<Option<Vec<i64>>>();
+        match parts.as_deref() {
+            Some(&[slot, block_index, outer_index]) => Ok(Self {
+                slot,
+                block_index,
+                outer_index,
+                inner_index: None,
+            }),
+            Some(&[slot, block_index, outer_index, inner_index]) => Ok(Self {
+                slot,
+                block_index,
+                outer_index,
+                inner_index: Some(inner_index),
+            }),
+            _ => Err(format!("Invalid instruction index {}", s)),
+        }
+    }
+}
+
+// cutoff for reconstructing a bonbon as it was at some point. instructions are replayed in order
+// and everything after the cutoff is dropped
+#[derive(Debug, Clone)]
+pub enum PointInTime {
+    // inclusive of every instruction in the slot
+    Slot(i64),
+
+    // inclusive of the instruction itself
+    Instruction(InstructionIndex),
+}
+
+impl PointInTime {
+    pub fn includes(&self, instruction_index: &InstructionIndex) -> bool {
+        match self {
+            PointInTime::Slot(slot) => instruction_index.slot <= *slot,
+            PointInTime::Instruction(cutoff) => instruction_index <= cutoff,
+        }
+    }
+}
+
+// the instructions that happened by `at` (all of them if None), in the order given. partitions
+// are read back sorted by (slot, block_index, outer_index, inner_index) where postgres puts an
+// outer instruction after its inner ones, but `InstructionIndex` orders it first. so a cutoff at
+// an outer instruction is preceded by inner instructions past it and the whole list has to be
+// checked instead of stopping at the first one that's excluded
+pub fn replay_until<'a, T: 'a, I>(
+    instructions: I,
+    at: Option<&'a PointInTime>,
+) -> impl Iterator<Item = (T, InstructionIndex)> + 'a
+where
+    I: IntoIterator<Item = (T, InstructionIndex)>,
+    I::IntoIter: 'a,
+{
+    instructions
+        .into_iter()
+        .filter(move |(_, index)| at.map_or(true, |at| at.includes(index)))
+}
+
 #[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
 #[derive(Default, Debug, Clone)]
 pub struct Ownership {
@@ -134,13 +212,171 @@ pub struct Ownership {
 }
 
 #[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
-#[derive(Default, Debug, Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferKind {
+    // MintTo / MintToChecked
+    Mint,
+
+    Transfer,
+
+    TransferChecked,
+
+    // SetAuthority with AccountOwner. the token account stays the same
+    OwnerChange,
+
+    // Burn / BurnChecked
+    Burn,
+
+    // token metadata BurnNft
+    BurnNft,
+}
+
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
 pub struct Transfer {
-    pub slot: i64,
+    pub instruction_index: InstructionIndex,
+
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<DisplayFromStr>")
+    )]
+    pub signature: Signature,
+
+    pub kind: TransferKind,
+
+    // the signer of the instruction. the owner, a delegate, the mint authority, etc
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<DisplayFromStr>")
+    )]
+    pub authority: Pubkey,
+
+    // whether the authority was someone other than the owner at the time. NB: this also picks up
+    // multisig owners since we don't resolve their signers
+    pub delegated: bool,
+
     pub start: Option<Ownership>, // first transfer starts for None
     pub end: Option<Ownership>,   // end can be None after burn
 }
 
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActivityKind {
+    // received a newly minted token
+    Mint,
+
+    Receive,
+
+    Send,
+
+    Burn,
+
+    // approved a delegate for the token account
+    Delegate,
+
+    // revoked the delegate
+    Revoke,
+
+    // signed as update authority
+    MetadataUpdate,
+
+    // signed as creator
+    CreatorVerify,
+
+    CreatorUnverify,
+}
+
+// something a wallet did (or had done to it) to this bonbon
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Activity {
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<DisplayFromStr>")
+    )]
+    pub wallet: Pubkey,
+
+    pub kind: ActivityKind,
+
+    // the other side of a send / receive, the delegate, etc
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<Option<DisplayFromStr>>")
+    )]
+    pub counterparty: Option<Pubkey>,
+
+    pub instruction_index: InstructionIndex,
+
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<DisplayFromStr>")
+    )]
+    pub signature: Signature,
+}
+
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AuthorityKind {
+    // can mint more supply
+    Mint,
+
+    // can freeze token accounts for the mint
+    Freeze,
+}
+
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AuthorityChange {
+    pub kind: AuthorityKind,
+
+    // None when the authority is revoked (or never set for freeze)
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<Option<DisplayFromStr>>")
+    )]
+    pub new_authority: Option<Pubkey>,
+
+    pub instruction_index: InstructionIndex,
+}
+
+// what produced a glazing. verifications copy the previous glazing and flip a single flag so we
+// keep the key around to tell them apart from the metadata data itself
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlazingSource {
+    // create / update metadata with the full data
+    Data,
+
+    // the master's data at the point the limited edition was printed
+    MasterEdition,
+
+    CreatorVerification {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        creator_key: Pubkey,
+
+        verified: bool,
+    },
+
+    CollectionVerification {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        collection_key: Pubkey,
+
+        verified: bool,
+    },
+}
+
+impl Default for GlazingSource {
+    fn default() -> Self {
+        Self::Data
+    }
+}
+
 #[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
 #[derive(Default, Debug, Clone)]
 pub struct Glazing {
@@ -150,11 +386,20 @@ pub struct Glazing {
 
     pub uri: String,
 
+    // u16 on-chain
+    pub seller_fee_basis_points: i32,
+
     pub creators: Vec<Creator>,
 
     pub collection: Option<Collection>,
 
+    // false for v1 data, which has no collection. updating with it leaves the collection as is
+    #[cfg_attr(feature = "serde-feature", serde(skip))]
+    pub sets_collection: bool,
+
     pub instruction_index: InstructionIndex,
+
+    pub source: GlazingSource,
 }
 
 #[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
@@ -172,11 +417,21 @@ pub struct Bonbon {
     )]
     pub metadata_key: Pubkey, // could be pubkey::default
 
+    // current authorities. see `authority_changes` for how we got here
     #[cfg_attr(
         feature = "serde-feature",
-        serde(with = "As::<DisplayFromStr>")
+        serde(with = "As::<Option<DisplayFromStr>>")
     )]
-    pub mint_authority: Pubkey, // could be pubkey::default
+    pub mint_authority: Option<Pubkey>,
+
+    #[cfg_attr(
+        feature = "serde-feature",
+        serde(with = "As::<Option<DisplayFromStr>>")
+    )]
+    pub freeze_authority: Option<Pubkey>,
+
+    // InitializeMint and SetAuthority for the mint and freeze authorities, in order
+    pub authority_changes: Vec<AuthorityChange>,
 
     pub transfers: Vec<Transfer>,
 
@@ -191,6 +446,9 @@ pub struct Bonbon {
     // limited edition
     pub glazings: Vec<Glazing>,
 
+    // per-wallet activity in the order it happened
+    pub activities: Vec<Activity>,
+
     // mapping of token account to owner
     // TODO: this is a bit of a hack. We need to track the owner of the token account and normally
     // rely on pre/postTokenBalances but those aren't available around block ~80M so...
@@ -207,11 +465,14 @@ impl std::fmt::Display for Bonbon {
             .field("mint_key", &self.mint_key)
             .field("metadata_key", &self.metadata_key)
             .field("mint_authority", &self.mint_authority)
+            .field("freeze_authority", &self.freeze_authority)
+            .field("authority_changes", &self.authority_changes)
             .field("transfers", &self.transfers)
             .field("current_owner", &self.current_owner)
             .field("edition_status", &self.edition_status)
             .field("limited_edition", &self.limited_edition)
             .field("glazings", &self.glazings)
+            .field("activities", &self.activities)
             .finish()
     }
 }
@@ -232,6 +493,7 @@ impl Bonbon {
                 }
             }
             next.instruction_index = instruction_index;
+            next.source = GlazingSource::CreatorVerification { creator_key, verified };
             self.glazings.push(next);
         } else {
             self.glazings.push(Glazing {
@@ -241,6 +503,7 @@ impl Bonbon {
                     share: 0,
                 }],
                 instruction_index,
+                source: GlazingSource::CreatorVerification { creator_key, verified },
                 ..Glazing::default()
             });
         }
@@ -263,14 +526,115 @@ impl Bonbon {
                 verified,
             }),
             instruction_index,
+            source: GlazingSource::CollectionVerification { collection_key, verified },
             ..prev
         })
     }
 
-    pub fn apply_ownership(&mut self, new_owner: Option<Ownership>, slot: i64) {
+    pub fn apply_authority(
+        &mut self,
+        kind: AuthorityKind,
+        new_authority: Option<Pubkey>,
+        instruction_index: InstructionIndex,
+    ) {
+        match kind {
+            AuthorityKind::Mint => self.mint_authority = new_authority,
+            AuthorityKind::Freeze => self.freeze_authority = new_authority,
+        }
+        self.authority_changes.push(AuthorityChange {
+            kind,
+            new_authority,
+            instruction_index,
+        });
+    }
+
+    // as of the latest glazing
+    pub fn collection(&self) -> Option<&Collection> {
+        self.glazings.last().and_then(|g| g.collection.as_ref())
+    }
+
+    pub fn push_data_glazing(&mut self, glazing: Glazing) {
+        let collection = if glazing.sets_collection {
+            glazing.collection
+        } else {
+            self.collection().cloned()
+        };
+        self.glazings.push(Glazing { collection, ..glazing });
+    }
+
+    // whether no one can mint more. None if we never saw the mint initialized
+    pub fn supply_fixed(&self) -> Option<bool> {
+        self.authority_changes
+            .iter()
+            .any(|c| c.kind == AuthorityKind::Mint)
+            .then(|| self.mint_authority.is_none())
+    }
+
+    pub fn record_activity(
+        &mut self,
+        wallet: Pubkey,
+        kind: ActivityKind,
+        counterparty: Option<Pubkey>,
+        instruction_index: InstructionIndex,
+        signature: Signature,
+    ) {
+        self.activities.push(Activity {
+            wallet,
+            kind,
+            counterparty,
+            instruction_index,
+            signature,
+        });
+    }
+
+    pub fn apply_ownership(
+        &mut self,
+        new_owner: Option<Ownership>,
+        kind: TransferKind,
+        authority: Pubkey,
+        instruction_index: InstructionIndex,
+        signature: Signature,
+    ) {
+        // only these can be signed by a delegate. everything else needs the owner (or mint
+        // authority) directly
+        let delegated = match kind {
+            TransferKind::Transfer | TransferKind::TransferChecked | TransferKind::Burn => {
+                self.current_owner.as_ref().map(|o| o.owner != authority).unwrap_or(false)
+            }
+            _ => false,
+        };
+
+        let start_owner = self.current_owner.as_ref().map(|o| o.owner);
+        let end_owner = new_owner.as_ref().map(|o| o.owner);
+        let mut record = |wallet, kind, counterparty| {
+            self.record_activity(wallet, kind, counterparty, instruction_index.clone(), signature)
+        };
+        match (kind, start_owner, end_owner) {
+            (TransferKind::Mint, _, Some(end_owner)) => {
+                record(end_owner, ActivityKind::Mint, None);
+            }
+            (TransferKind::Burn | TransferKind::BurnNft, Some(start_owner), _) => {
+                record(start_owner, ActivityKind::Burn, None);
+            }
+            // BurnNft after the inner Burn has nothing left to burn
+            (TransferKind::Burn | TransferKind::BurnNft, None, _) => {}
+            (_, start_owner, end_owner) => {
+                if let Some(start_owner) = start_owner {
+                    record(start_owner, ActivityKind::Send, end_owner);
+                }
+                if let Some(end_owner) = end_owner {
+                    record(end_owner, ActivityKind::Receive, start_owner);
+                }
+            }
+        }
+
         if let Some(current_owner) = &self.current_owner {
             let t = Transfer {
-                slot,
+                instruction_index,
+                signature,
+                kind,
+                authority,
+                delegated,
                 start: Some(current_owner.clone()),
                 end: new_owner.clone(),
             };
@@ -280,7 +644,11 @@ impl Bonbon {
             // first transfer
             let o = new_owner;
             let t = Transfer {
-                slot,
+                instruction_index,
+                signature,
+                kind,
+                authority,
+                delegated,
                 start: None,
                 end: o.clone(),
             };
@@ -364,6 +732,8 @@ pub struct InstructionContext<'a, T: Cocoa> {
 
     pub instruction_index: InstructionIndex,
 
+    pub signature: Signature,
+
     pub transient_metas: &'a mut Vec<TransactionTokenOwnerMeta>,
 }
 
@@ -377,9 +747,12 @@ impl IntoGlazing for mpl_token_metadata::state::Data {
             name: self.name,
             symbol: self.symbol,
             uri: self.uri,
+            seller_fee_basis_points: self.seller_fee_basis_points.into(),
             creators: from_creators(self.creators),
             collection: None,
+            sets_collection: false,
             instruction_index,
+            source: GlazingSource::Data,
         }
     }
 }
@@ -390,9 +763,12 @@ impl IntoGlazing for mpl_token_metadata::state::DataV2 {
             name: self.name,
             symbol: self.symbol,
             uri: self.uri,
+            seller_fee_basis_points: self.seller_fee_basis_points.into(),
             creators: from_creators(self.creators),
             collection: self.collection.map(Collection::from),
+            sets_collection: true,
             instruction_index,
+            source: GlazingSource::Data,
         }
     }
 }
@@ -404,6 +780,7 @@ pub fn update_metadata_instruction<T: Cocoa>(
         account_keys,
         owners: _,
         instruction_index,
+        signature,
         transient_metas: _,
     }: InstructionContext<T>,
 ) -> Result<(), ErrorCode> {
@@ -438,8 +815,15 @@ pub fn update_metadata_instruction<T: Cocoa>(
                 return Err(ErrorCode::InvalidMetadataUpdate);
             }
 
+            bonbon.record_activity(
+                get_account_key(1)?,
+                ActivityKind::MetadataUpdate,
+                None,
+                instruction_index.clone(),
+                signature,
+            );
             if let Some(data) = args.data {
-                bonbon.glazings.push(data.into_glazing(instruction_index));
+                bonbon.push_data_glazing(data.into_glazing(instruction_index));
             }
         }
         MetadataInstruction::UpdateMetadataAccountV2(args) => {
@@ -448,8 +832,15 @@ pub fn update_metadata_instruction<T: Cocoa>(
                 return Err(ErrorCode::InvalidMetadataUpdate);
             }
 
+            bonbon.record_activity(
+                get_account_key(1)?,
+                ActivityKind::MetadataUpdate,
+                None,
+                instruction_index.clone(),
+                signature,
+            );
             if let Some(data) = args.data {
-                bonbon.glazings.push(data.into_glazing(instruction_index));
+                bonbon.push_data_glazing(data.into_glazing(instruction_index));
             }
         }
         MetadataInstruction::DeprecatedCreateMasterEdition(_) => {
@@ -528,6 +919,13 @@ pub fn update_metadata_instruction<T: Cocoa>(
             }
 
             let creator_key = get_account_key(1)?;
+            bonbon.record_activity(
+                creator_key,
+                ActivityKind::CreatorVerify,
+                None,
+                instruction_index.clone(),
+                signature,
+            );
             bonbon.apply_creator_verification(creator_key, true, instruction_index);
         }
         MetadataInstruction::RemoveCreatorVerification => {
@@ -537,6 +935,13 @@ pub fn update_metadata_instruction<T: Cocoa>(
             }
 
             let creator_key = get_account_key(1)?;
+            bonbon.record_activity(
+                creator_key,
+                ActivityKind::CreatorUnverify,
+                None,
+                instruction_index.clone(),
+                signature,
+            );
             bonbon.apply_creator_verification(creator_key, false, instruction_index);
         }
         MetadataInstruction::VerifyCollection => {
@@ -567,7 +972,13 @@ pub fn update_metadata_instruction<T: Cocoa>(
             bonbon.apply_collection_verification(collection_key, false, instruction_index);
         }
         MetadataInstruction::BurnNft => {
-            bonbon.apply_ownership(None, instruction_index.slot);
+            bonbon.apply_ownership(
+                None,
+                TransferKind::BurnNft,
+                get_account_key(1)?,
+                instruction_index,
+                signature,
+            );
         }
         MetadataInstruction::VerifySizedCollectionItem => {
             let metadata_key = get_account_key(0)?;
@@ -636,6 +1047,7 @@ pub fn update_token_instruction<T: Cocoa>(
         account_keys,
         owners,
         instruction_index,
+        signature,
         transient_metas,
     }: InstructionContext<T>,
 ) -> Result<(), ErrorCode> {
@@ -654,8 +1066,18 @@ pub fn update_token_instruction<T: Cocoa>(
     let token_instruction = instruction.bake()?;
 
     match token_instruction {
-        TokenInstruction::InitializeMint { .. } => {
+        TokenInstruction::InitializeMint { mint_authority, freeze_authority, .. } => {
             bonbon.mint_key = get_account_key(0)?;
+            bonbon.apply_authority(
+                AuthorityKind::Mint,
+                Some(mint_authority),
+                instruction_index.clone(),
+            );
+            bonbon.apply_authority(
+                AuthorityKind::Freeze,
+                freeze_authority.into(),
+                instruction_index,
+            );
         }
         // initializing an account doesn't change who currently owns it
         TokenInstruction::InitializeAccount { .. } => {
@@ -697,7 +1119,10 @@ pub fn update_token_instruction<T: Cocoa>(
                         .ok_or(ErrorCode::CouldNotFindTokenAccountOwner)?,
                     account: new_account,
                 }),
-                instruction_index.slot,
+                TransferKind::Transfer,
+                get_account_key(2)?,
+                instruction_index,
+                signature,
             );
         }
         TokenInstruction::SetAuthority {
@@ -713,10 +1138,28 @@ pub fn update_token_instruction<T: Cocoa>(
                                 owner: new_authority,
                                 account: get_account_key(0)?,
                             }),
-                            instruction_index.slot,
+                            TransferKind::OwnerChange,
+                            get_account_key(1)?,
+                            instruction_index,
+                            signature,
                         );
                     }
                 }
+                // account 0 is the mint for these
+                AuthorityType::MintTokens => {
+                    bonbon.apply_authority(
+                        AuthorityKind::Mint,
+                        new_authority.into(),
+                        instruction_index,
+                    );
+                }
+                AuthorityType::FreezeAccount => {
+                    bonbon.apply_authority(
+                        AuthorityKind::Freeze,
+                        new_authority.into(),
+                        instruction_index,
+                    );
+                }
                 _ => {}
             }
         }
@@ -730,12 +1173,24 @@ pub fn update_token_instruction<T: Cocoa>(
                         .ok_or(ErrorCode::CouldNotFindTokenAccountOwner)?,
                     account: new_account,
                 }),
-                instruction_index.slot,
+                TransferKind::Mint,
+                get_account_key(2)?,
+                instruction_index,
+                signature,
             );
-            bonbon.mint_authority = get_account_key(2)?;
+            // we might have missed the InitializeMint but the mint authority had to sign
+            if bonbon.supply_fixed().is_none() {
+                bonbon.mint_authority = Some(get_account_key(2)?);
+            }
         }
         TokenInstruction::Burn { .. } => {
-            bonbon.apply_ownership(None, instruction_index.slot);
+            bonbon.apply_ownership(
+                None,
+                TransferKind::Burn,
+                get_account_key(2)?,
+                instruction_index,
+                signature,
+            );
         }
         TokenInstruction::TransferChecked { .. } => {
             let new_owner = get_token_meta_for(2)?.owner_key;
@@ -747,7 +1202,10 @@ pub fn update_token_instruction<T: Cocoa>(
                         .ok_or(ErrorCode::CouldNotFindTokenAccountOwner)?,
                     account: new_account,
                 }),
-                instruction_index.slot,
+                TransferKind::TransferChecked,
+                get_account_key(3)?,
+                instruction_index,
+                signature,
             );
         }
         TokenInstruction::MintToChecked { .. } => {
@@ -760,16 +1218,44 @@ pub fn update_token_instruction<T: Cocoa>(
                         .ok_or(ErrorCode::CouldNotFindTokenAccountOwner)?,
                     account: new_account,
                 }),
-                instruction_index.slot,
+                TransferKind::Mint,
+                get_account_key(2)?,
+                instruction_index,
+                signature,
             );
-            bonbon.mint_authority = get_account_key(2)?;
+            // we might have missed the InitializeMint but the mint authority had to sign
+            if bonbon.supply_fixed().is_none() {
+                bonbon.mint_authority = Some(get_account_key(2)?);
+            }
         }
         TokenInstruction::BurnChecked { .. } => {
-            bonbon.apply_ownership(None, instruction_index.slot);
+            bonbon.apply_ownership(
+                None,
+                TransferKind::Burn,
+                get_account_key(2)?,
+                instruction_index,
+                signature,
+            );
         }
         TokenInstruction::InitializeMultisig { .. } => {}
-        TokenInstruction::Approve { .. } => {}
-        TokenInstruction::Revoke => {}
+        TokenInstruction::Approve { .. } => {
+            bonbon.record_activity(
+                get_account_key(2)?,
+                ActivityKind::Delegate,
+                Some(get_account_key(1)?),
+                instruction_index,
+                signature,
+            );
+        }
+        TokenInstruction::Revoke => {
+            bonbon.record_activity(
+                get_account_key(1)?,
+                ActivityKind::Revoke,
+                None,
+                instruction_index,
+                signature,
+            );
+        }
         TokenInstruction::CloseAccount => {
             // mints can't be closed and a token account must have zero balance to be closed so...
             bonbon.ownerships.remove(&get_account_key(0)?);
@@ -783,7 +1269,15 @@ pub fn update_token_instruction<T: Cocoa>(
         }
         TokenInstruction::FreezeAccount => {}
         TokenInstruction::ThawAccount => {}
-        TokenInstruction::ApproveChecked { .. } => {}
+        TokenInstruction::ApproveChecked { .. } => {
+            bonbon.record_activity(
+                get_account_key(3)?,
+                ActivityKind::Delegate,
+                Some(get_account_key(2)?),
+                instruction_index,
+                signature,
+            );
+        }
         TokenInstruction::SyncNative => {}
         TokenInstruction::InitializeAccount3 { owner: owner_key } => {
             let account_key = get_account_key(0)?;
@@ -799,8 +1293,18 @@ pub fn update_token_instruction<T: Cocoa>(
             }
         }
         TokenInstruction::InitializeMultisig2 { .. } => {}
-        TokenInstruction::InitializeMint2 { .. } => {
+        TokenInstruction::InitializeMint2 { mint_authority, freeze_authority, .. } => {
             bonbon.mint_key = get_account_key(0)?;
+            bonbon.apply_authority(
+                AuthorityKind::Mint,
+                Some(mint_authority),
+                instruction_index.clone(),
+            );
+            bonbon.apply_authority(
+                AuthorityKind::Freeze,
+                freeze_authority.into(),
+                instruction_index,
+            );
         }
 
         // none of the token-2022 info gets passed out of partition
@@ -859,16 +1363,13 @@ pub fn glaze_limited<T: Cocoa>(
     master_instructions: &[(T, InstructionIndex)],
 ) -> Result<(), ErrorCode> {
     if let Some(limited_edition) = &mut bonbon.limited_edition {
-        let mut latest_glazing = None;
-        for (instruction, index) in master_instructions {
-            let index = index.clone();
-
-            // limited edition metadata does not get updated as the master changes
-            // i.e it is final at point of limited edition creation
-            if index > limited_edition.instruction_index {
-                break;
-            }
+        // limited edition metadata does not get updated as the master changes
+        // i.e it is final at point of limited edition creation
+        let cutoff = PointInTime::Instruction(limited_edition.instruction_index.clone());
 
+        let mut latest_glazing = None;
+        let master_instructions = master_instructions.iter().map(|(i, index)| (i, index.clone()));
+        for (instruction, index) in replay_until(master_instructions, Some(&cutoff)) {
             let metadata_instruction = instruction.roast()?;
             let glazing = match metadata_instruction {
                 MetadataInstruction::CreateMetadataAccount(args) => Some(args.data.into_glazing(index)),
@@ -880,15 +1381,188 @@ pub fn glaze_limited<T: Cocoa>(
             };
 
             // since these are sorted, just update the latest
-            if glazing.is_some() {
-                latest_glazing = glazing;
+            if let Some(glazing) = glazing {
+                let collection = if glazing.sets_collection {
+                    glazing.collection
+                } else {
+                    latest_glazing.and_then(|g: Glazing| g.collection)
+                };
+                latest_glazing = Some(Glazing { collection, ..glazing });
             }
         }
 
+        // the print's own metadata account can still be updated and (un)verified afterwards.
+        // those were applied on top of nothing while assembling, so replay them on top of the
+        // inherited glazing
+        let mut own_glazings = std::mem::take(&mut bonbon.glazings);
+        own_glazings.sort_by(|a, b| a.instruction_index.cmp(&b.instruction_index));
+
         if let Some(glazing) = latest_glazing {
-            bonbon.glazings.push(glazing);
+            bonbon.glazings.push(Glazing {
+                sets_collection: true,
+                source: GlazingSource::MasterEdition,
+                ..glazing
+            });
+        }
+
+        for glazing in own_glazings {
+            match glazing.source {
+                // a v1 update keeps the inherited collection
+                GlazingSource::Data | GlazingSource::MasterEdition => {
+                    bonbon.push_data_glazing(glazing);
+                }
+                GlazingSource::CreatorVerification { creator_key, verified } => {
+                    bonbon.apply_creator_verification(
+                        creator_key, verified, glazing.instruction_index);
+                }
+                GlazingSource::CollectionVerification { collection_key, verified } => {
+                    bonbon.apply_collection_verification(
+                        collection_key, verified, glazing.instruction_index);
+                }
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        mpl_token_metadata::{
+            instruction::{CreateMetadataAccountArgsV2, UpdateMetadataAccountArgs},
+            state::{Data, DataV2},
+        },
+    };
+
+    fn index(slot: i64, outer_index: i64, inner_index: Option<i64>) -> InstructionIndex {
+        InstructionIndex { slot, block_index: 0, outer_index, inner_index }
+    }
+
+    // in the order the partitions query returns them, inner instructions before their outer one
+    fn instructions() -> Vec<(&'static str, InstructionIndex)> {
+        vec![
+            ("1.0.0", index(1, 0, Some(0))),
+            ("1.0.1", index(1, 0, Some(1))),
+            ("1.0", index(1, 0, None)),
+            ("1.1", index(1, 1, None)),
+            ("2.0", index(2, 0, None)),
+        ]
+    }
+
+    fn replayed(at: Option<&PointInTime>) -> Vec<&'static str> {
+        replay_until(instructions(), at).map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn replay_until_outer_instruction_with_inner_instructions() {
+        let at = PointInTime::Instruction(index(1, 0, None));
+        assert_eq!(replayed(Some(&at)), vec!["1.0"]);
+    }
+
+    #[test]
+    fn replay_until_inner_instruction() {
+        let at = PointInTime::Instruction(index(1, 0, Some(0)));
+        assert_eq!(replayed(Some(&at)), vec!["1.0.0", "1.0"]);
+    }
+
+    #[test]
+    fn instruction_index_round_trip() {
+        for s in ["1.2.3", "1.2.3.4"] {
+            assert_eq!(s.parse::<InstructionIndex>().unwrap().to_string(), s);
+        }
+        for s in ["", "1.2", "1.2.3.4.5", "1.-2.3", "1..3", "a.b.c"] {
+            assert!(s.parse::<InstructionIndex>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn replay_until_slot() {
+        assert_eq!(replayed(Some(&PointInTime::Slot(1))), vec!["1.0.0", "1.0.1", "1.0", "1.1"]);
+        assert_eq!(replayed(Some(&PointInTime::Slot(0))), Vec::<&str>::new());
+        assert_eq!(replayed(None).len(), 5);
+    }
+
+    // metadata instructions that are already unpacked
+    struct Roasted(MetadataInstruction);
+
+    impl Cocoa for Roasted {
+        fn program_key(&self, _account_keys: &[Pubkey]) -> Result<Pubkey, ErrorCode> {
+            Ok(mpl_token_metadata::id())
+        }
+
+        fn account_index(&self, _index: usize) -> Result<u8, ErrorCode> {
+            Err(ErrorCode::BadAccountKeyIndex)
+        }
+
+        fn roast(&self) -> Result<MetadataInstruction, ErrorCode> {
+            Ok(self.0.clone())
+        }
+
+        fn bake(&self) -> Result<TokenInstruction, ErrorCode> {
+            Err(ErrorCode::FailedInstructionDeserialization)
+        }
+    }
+
+    fn v1_data(name: &str) -> Data {
+        Data {
+            name: name.to_string(),
+            symbol: String::new(),
+            uri: String::new(),
+            seller_fee_basis_points: 0,
+            creators: None,
+        }
+    }
+
+    #[test]
+    fn glaze_limited_keeps_collection_through_v1_updates() {
+        let collection_key = Pubkey::new_from_array([1; 32]);
+        let master_instructions = vec![
+            (
+                Roasted(MetadataInstruction::CreateMetadataAccountV2(CreateMetadataAccountArgsV2 {
+                    data: DataV2 {
+                        name: "master".to_string(),
+                        symbol: String::new(),
+                        uri: String::new(),
+                        seller_fee_basis_points: 0,
+                        creators: None,
+                        collection: Some(MplCollection { verified: true, key: collection_key }),
+                        uses: None,
+                    },
+                    is_mutable: true,
+                })),
+                index(1, 0, None),
+            ),
+            (
+                Roasted(MetadataInstruction::UpdateMetadataAccount(UpdateMetadataAccountArgs {
+                    data: Some(v1_data("renamed")),
+                    update_authority: None,
+                    primary_sale_happened: None,
+                })),
+                index(2, 0, None),
+            ),
+        ];
+
+        let mut bonbon = Bonbon {
+            limited_edition: Some(LimitedEdition {
+                master_key: Pubkey::new_from_array([2; 32]),
+                edition_num: None,
+                instruction_index: index(3, 0, None),
+            }),
+            ..Bonbon::default()
+        };
+        // the print's own update, assembled before the master's glazing is known
+        bonbon.push_data_glazing(v1_data("print").into_glazing(index(4, 0, None)));
+
+        glaze_limited(&mut bonbon, &master_instructions).unwrap();
+
+        let glazings = bonbon.glazings.iter()
+            .map(|g| (g.name.as_str(), g.collection.as_ref().map(|c| c.address)))
+            .collect::<Vec<_>>();
+        assert_eq!(glazings, vec![
+            ("renamed", Some(collection_key)),
+            ("print", Some(collection_key)),
+        ]);
+    }
+}
@@ -8,6 +8,7 @@ use {
         transaction::VersionedTransaction,
     },
     solana_transaction_status::{
+        ConfirmedBlock,
         EncodedTransaction,
         EncodedTransactionWithStatusMeta,
         InnerInstructions,
@@ -18,6 +19,7 @@ use {
         UiLoadedAddresses,
         UiMessage,
         UiAddressTableLookup,
+        UiConfirmedBlock,
         UiInnerInstructions,
         UiInstruction,
         UiTransaction,
@@ -28,6 +30,7 @@ use {
     std::str::FromStr,
 };
 
+#[derive(Debug)]
 pub enum ConversionError {
     BinaryDecodingFailed,
 
@@ -42,6 +45,9 @@ pub enum ConversionError {
     UnexpectedUiParsed,
 
     NonLegacyMissingMeta,
+
+    // block was fetched without full transaction details
+    MissingTransactions,
 }
 
 pub fn convert_hash(s: &str) -> Result<Hash, ConversionError> {
@@ -213,3 +219,22 @@ pub fn convert(
     }
 }
 
+
+pub fn convert_block(
+    block: UiConfirmedBlock,
+) -> Result<ConfirmedBlock, ConversionError> {
+    Ok(ConfirmedBlock {
+        previous_blockhash: block.previous_blockhash,
+        blockhash: block.blockhash,
+        parent_slot: block.parent_slot,
+        transactions: block
+            .transactions
+            .ok_or(ConversionError::MissingTransactions)?
+            .into_iter()
+            .map(convert)
+            .collect::<Result<Vec<_>, _>>()?,
+        rewards: block.rewards.unwrap_or_default(),
+        block_time: block.block_time,
+        block_height: block.block_height,
+    })
+}
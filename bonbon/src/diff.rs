@@ -0,0 +1,297 @@
+use {
+    crate::assemble::{Bonbon, Glazing, GlazingSource, InstructionIndex},
+    solana_sdk::pubkey::Pubkey,
+};
+
+#[cfg(feature = "serde-feature")]
+use {
+    serde_with::{As, DisplayFromStr},
+    serde::{Deserialize, Serialize},
+};
+
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlazingChange {
+    Name {
+        from: String,
+
+        to: String,
+    },
+
+    Symbol {
+        from: String,
+
+        to: String,
+    },
+
+    Uri {
+        from: String,
+
+        to: String,
+    },
+
+    CreatorAdded {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        address: Pubkey,
+
+        share: i16,
+
+        verified: bool,
+    },
+
+    CreatorRemoved {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        address: Pubkey,
+    },
+
+    CreatorShareChanged {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        address: Pubkey,
+
+        from: i16,
+
+        to: i16,
+    },
+
+    CreatorVerified {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        address: Pubkey,
+
+        verified: bool,
+    },
+
+    CollectionChanged {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<Option<DisplayFromStr>>")
+        )]
+        from: Option<Pubkey>,
+
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<Option<DisplayFromStr>>")
+        )]
+        to: Option<Pubkey>,
+    },
+
+    CollectionVerified {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        address: Pubkey,
+
+        verified: bool,
+    },
+
+    RoyaltyChanged {
+        from: i32,
+
+        to: i32,
+    },
+}
+
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct GlazingEvent {
+    // of the glazing that made the change
+    pub instruction_index: InstructionIndex,
+
+    pub change: GlazingChange,
+}
+
+// strings are zero-padded on-chain and the padding isn't consistent between create and update
+fn trimmed(s: &str) -> &str {
+    s.trim_matches(char::from(0))
+}
+
+pub fn diff_glazings(prev: &Glazing, next: &Glazing) -> Vec<GlazingChange> {
+    let mut changes = diff_data(prev, next);
+    changes.extend(diff_verifications(prev, next));
+    changes
+}
+
+// name, symbol, uri, royalty and the creator list. only meaningful between glazings that carry
+// the full data: a verification before any data glazing is applied on top of defaults
+fn diff_data(prev: &Glazing, next: &Glazing) -> Vec<GlazingChange> {
+    let mut changes = vec![];
+
+    let string_change = |from: &str, to: &str| {
+        (trimmed(from) != trimmed(to))
+            .then(|| (trimmed(from).to_string(), trimmed(to).to_string()))
+    };
+
+    if let Some((from, to)) = string_change(&prev.name, &next.name) {
+        changes.push(GlazingChange::Name { from, to });
+    }
+    if let Some((from, to)) = string_change(&prev.symbol, &next.symbol) {
+        changes.push(GlazingChange::Symbol { from, to });
+    }
+    if let Some((from, to)) = string_change(&prev.uri, &next.uri) {
+        changes.push(GlazingChange::Uri { from, to });
+    }
+
+    if prev.seller_fee_basis_points != next.seller_fee_basis_points {
+        changes.push(GlazingChange::RoyaltyChanged {
+            from: prev.seller_fee_basis_points,
+            to: next.seller_fee_basis_points,
+        });
+    }
+
+    for creator in &prev.creators {
+        if !next.creators.iter().any(|c| c.address == creator.address) {
+            changes.push(GlazingChange::CreatorRemoved { address: creator.address });
+        }
+    }
+
+    for creator in &next.creators {
+        match prev.creators.iter().find(|c| c.address == creator.address) {
+            Some(prev_creator) => {
+                if prev_creator.share != creator.share {
+                    changes.push(GlazingChange::CreatorShareChanged {
+                        address: creator.address,
+                        from: prev_creator.share,
+                        to: creator.share,
+                    });
+                }
+            }
+            None => {
+                changes.push(GlazingChange::CreatorAdded {
+                    address: creator.address,
+                    share: creator.share,
+                    verified: creator.verified,
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+// creator and collection verification, which every glazing tracks
+fn diff_verifications(prev: &Glazing, next: &Glazing) -> Vec<GlazingChange> {
+    let mut changes = vec![];
+
+    for creator in &next.creators {
+        let prev_creator = prev.creators.iter().find(|c| c.address == creator.address);
+        if prev_creator.map_or(false, |c| c.verified != creator.verified) {
+            changes.push(GlazingChange::CreatorVerified {
+                address: creator.address,
+                verified: creator.verified,
+            });
+        }
+    }
+
+    let prev_collection = prev.collection.as_ref();
+    let next_collection = next.collection.as_ref();
+    if prev_collection.map(|c| c.address) != next_collection.map(|c| c.address) {
+        changes.push(GlazingChange::CollectionChanged {
+            from: prev_collection.map(|c| c.address),
+            to: next_collection.map(|c| c.address),
+        });
+        // e.g SetAndVerifyCollection
+        if let Some(collection) = next_collection.filter(|c| c.verified) {
+            changes.push(GlazingChange::CollectionVerified {
+                address: collection.address,
+                verified: true,
+            });
+        }
+    } else if let (Some(prev_collection), Some(next_collection)) =
+        (prev_collection, next_collection)
+    {
+        if prev_collection.verified != next_collection.verified {
+            changes.push(GlazingChange::CollectionVerified {
+                address: next_collection.address,
+                verified: next_collection.verified,
+            });
+        }
+    }
+
+    changes
+}
+
+impl Bonbon {
+    // changes between consecutive glazings. the first glazing is the starting point so doesn't
+    // produce any events itself. data fields are only compared between glazings that carry the
+    // data, so a verification glazing doesn't show up as e.g a royalty change from 0
+    pub fn glazing_events(&self) -> Vec<GlazingEvent> {
+        let has_data = |glazing: &Glazing| matches!(
+            glazing.source, GlazingSource::Data | GlazingSource::MasterEdition);
+
+        let mut events = vec![];
+        let mut last_data = None;
+        for pair in self.glazings.windows(2) {
+            if has_data(&pair[0]) {
+                last_data = Some(&pair[0]);
+            }
+
+            let mut changes = vec![];
+            if let Some(last_data) = last_data.filter(|_| has_data(&pair[1])) {
+                changes.extend(diff_data(last_data, &pair[1]));
+            }
+            changes.extend(diff_verifications(&pair[0], &pair[1]));
+
+            events.extend(changes.into_iter().map(|change| GlazingEvent {
+                instruction_index: pair[1].instruction_index.clone(),
+                change,
+            }));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::assemble::Creator};
+
+    fn data_glazing(slot: i64, verified: bool) -> Glazing {
+        Glazing {
+            name: "bonbon".to_string(),
+            seller_fee_basis_points: 500,
+            creators: vec![Creator { address: Pubkey::new_from_array([1; 32]), verified, share: 100 }],
+            instruction_index: InstructionIndex { slot, ..InstructionIndex::default() },
+            source: GlazingSource::Data,
+            ..Glazing::default()
+        }
+    }
+
+    #[test]
+    fn verification_before_data_is_not_a_data_change() {
+        let creator_key = Pubkey::new_from_array([1; 32]);
+        let mut bonbon = Bonbon::default();
+        bonbon.apply_creator_verification(
+            creator_key, true, InstructionIndex { slot: 1, ..InstructionIndex::default() });
+        bonbon.glazings.push(data_glazing(2, true));
+
+        let changes = bonbon.glazing_events().into_iter().map(|e| e.change).collect::<Vec<_>>();
+        assert_eq!(changes, vec![]);
+    }
+
+    #[test]
+    fn data_changes_skip_verification_glazings() {
+        let creator_key = Pubkey::new_from_array([1; 32]);
+        let mut bonbon = Bonbon::default();
+        bonbon.glazings.push(data_glazing(1, false));
+        bonbon.apply_creator_verification(
+            creator_key, true, InstructionIndex { slot: 2, ..InstructionIndex::default() });
+        bonbon.glazings.push(Glazing { seller_fee_basis_points: 250, ..data_glazing(3, true) });
+
+        let changes = bonbon.glazing_events().into_iter().map(|e| e.change).collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            GlazingChange::CreatorVerified { address: creator_key, verified: true },
+            GlazingChange::RoyaltyChanged { from: 500, to: 250 },
+        ]);
+    }
+}
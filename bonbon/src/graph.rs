@@ -0,0 +1,352 @@
+use {
+    crate::assemble::{AuthorityKind, Bonbon, InstructionIndex, TransferKind},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    solana_transaction_status::TransactionWithStatusMeta,
+    std::collections::{BTreeMap, HashMap, HashSet},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeKind {
+    Wallet,
+
+    TokenAccount,
+
+    // source of mints and sink of burns. also where authority history starts
+    Mint,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub key: Pubkey,
+
+    pub kind: NodeKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    // wallet -> wallet. mints start at the mint and burns end at it
+    Transfer(TransferKind),
+
+    // wallet -> token account, the first time the wallet is seen owning it
+    Holds,
+
+    // previous authority -> new authority. the first starts at the mint and a revoke ends at it
+    Authority(AuthorityKind),
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub source: Pubkey,
+
+    pub target: Pubkey,
+
+    pub kind: EdgeKind,
+
+    pub mint_key: Pubkey,
+
+    pub instruction_index: InstructionIndex,
+
+    // authority changes don't keep their signature
+    pub signature: Option<Signature>,
+
+    pub delegated: bool,
+
+    // what the new owner paid the previous one for a transfer, from the transaction's balances.
+    // see `sale_lamports`. None until `add_sale_lamports` and when there's no payment to see
+    pub lamports: Option<u64>,
+}
+
+impl Edge {
+    // wallet to wallet transfers, which is where a sale would show up
+    fn is_transfer_between_wallets(&self) -> bool {
+        matches!(
+            self.kind,
+            EdgeKind::Transfer(
+                TransferKind::Transfer | TransferKind::TransferChecked | TransferKind::OwnerChange
+            )
+        )
+    }
+}
+
+// lamports `buyer` paid for a token `seller` transferred to it in `transaction`: how much the
+// buyer's balance went down, less the fee if it paid it. there's no marketplace decoding so this
+// only counts when the seller's balance went up in the same transaction, which leaves out plain
+// transfers and sales settled through an escrow. royalties and marketplace fees come out of what
+// the buyer paid, so the seller gets less than this
+pub fn sale_lamports(
+    transaction: &TransactionWithStatusMeta,
+    seller: &Pubkey,
+    buyer: &Pubkey,
+) -> Option<u64> {
+    let meta = match transaction {
+        TransactionWithStatusMeta::Complete(transaction) => &transaction.meta,
+        TransactionWithStatusMeta::MissingMetadata(_) => return None,
+    };
+    if seller == buyer || meta.status.is_err() {
+        return None;
+    }
+    let account_keys = transaction.account_keys();
+    let position = |key: &Pubkey| account_keys.iter().position(|k| k == key);
+    let delta = |index: usize| -> Option<i128> {
+        Some(i128::from(*meta.post_balances.get(index)?)
+             - i128::from(*meta.pre_balances.get(index)?))
+    };
+    let (seller_index, buyer_index) = (position(seller)?, position(buyer)?);
+    let fee = if buyer_index == 0 { i128::from(meta.fee) } else { 0 };
+    let paid = -delta(buyer_index)? - fee;
+    if delta(seller_index)? > 0 && paid > 0 {
+        u64::try_from(paid).ok()
+    } else {
+        None
+    }
+}
+
+// ownership (and authority) history of one or more bonbons. nodes are shared between bonbons so a
+// collection export shows wallets trading between each other
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub nodes: BTreeMap<Pubkey, Node>,
+
+    pub edges: Vec<Edge>,
+
+    holds: HashSet<(Pubkey, Pubkey)>,
+}
+
+impl Graph {
+    fn add_node(&mut self, key: Pubkey, kind: NodeKind) {
+        self.nodes.entry(key).or_insert(Node { key, kind });
+    }
+
+    pub fn add_bonbon(&mut self, bonbon: &Bonbon) {
+        let mint_key = bonbon.mint_key;
+        self.add_node(mint_key, NodeKind::Mint);
+
+        for transfer in &bonbon.transfers {
+            let source = transfer.start.as_ref().map(|s| s.owner).unwrap_or(mint_key);
+            let target = transfer.end.as_ref().map(|e| e.owner).unwrap_or(mint_key);
+            self.add_node(source, NodeKind::Wallet);
+            self.add_node(target, NodeKind::Wallet);
+
+            if let Some(end) = &transfer.end {
+                self.add_node(end.account, NodeKind::TokenAccount);
+                if self.holds.insert((end.owner, end.account)) {
+                    self.edges.push(Edge {
+                        source: end.owner,
+                        target: end.account,
+                        kind: EdgeKind::Holds,
+                        mint_key,
+                        instruction_index: transfer.instruction_index.clone(),
+                        signature: Some(transfer.signature),
+                        delegated: false,
+                        lamports: None,
+                    });
+                }
+            }
+
+            self.edges.push(Edge {
+                source,
+                target,
+                kind: EdgeKind::Transfer(transfer.kind),
+                mint_key,
+                instruction_index: transfer.instruction_index.clone(),
+                signature: Some(transfer.signature),
+                delegated: transfer.delegated,
+                lamports: None,
+            });
+        }
+
+        let mut authorities = HashMap::new();
+        for change in &bonbon.authority_changes {
+            let source = authorities.get(&change.kind).copied().unwrap_or(mint_key);
+            let target = change.new_authority.unwrap_or(mint_key);
+            self.add_node(target, NodeKind::Wallet);
+            self.edges.push(Edge {
+                source,
+                target,
+                kind: EdgeKind::Authority(change.kind),
+                mint_key,
+                instruction_index: change.instruction_index.clone(),
+                signature: None,
+                delegated: false,
+                lamports: None,
+            });
+            authorities.insert(change.kind, target);
+        }
+    }
+    // signatures of the transfers `add_sale_lamports` would look at
+    pub fn transfer_signatures(&self) -> HashSet<Signature> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.is_transfer_between_wallets())
+            .filter_map(|edge| edge.signature)
+            .collect()
+    }
+
+    // prices the wallet to wallet transfers whose transactions are in `transactions`
+    pub fn add_sale_lamports(
+        &mut self,
+        transactions: &HashMap<Signature, TransactionWithStatusMeta>,
+    ) {
+        for edge in self.edges.iter_mut().filter(|edge| edge.is_transfer_between_wallets()) {
+            if let Some(transaction) = edge.signature.and_then(|s| transactions.get(&s)) {
+                edge.lamports = sale_lamports(transaction, &edge.source, &edge.target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::assemble::{AuthorityChange, Ownership, Transfer},
+        solana_sdk::{
+            message::{Message, VersionedMessage},
+            transaction::VersionedTransaction,
+        },
+        solana_transaction_status::{TransactionStatusMeta, VersionedTransactionWithStatusMeta},
+    };
+
+    fn key(n: u8) -> Pubkey {
+        Pubkey::new_from_array([n; 32])
+    }
+
+    fn index(slot: i64) -> InstructionIndex {
+        InstructionIndex { slot, block_index: 0, outer_index: 0, inner_index: None }
+    }
+
+    fn transfer(
+        slot: i64,
+        kind: TransferKind,
+        start: Option<(u8, u8)>,
+        end: Option<(u8, u8)>,
+    ) -> Transfer {
+        let ownership = |(owner, account): (u8, u8)| Ownership {
+            owner: key(owner),
+            account: key(account),
+        };
+        Transfer {
+            instruction_index: index(slot),
+            signature: Signature::new(&[slot as u8; 64]),
+            kind,
+            authority: key(0),
+            delegated: false,
+            start: start.map(ownership),
+            end: end.map(ownership),
+        }
+    }
+
+    // minted to wallet 1, sold to wallet 2 which burns it. the mint authority goes to wallet 1
+    // and is revoked
+    fn bonbon() -> Bonbon {
+        let mut bonbon = Bonbon::default();
+        bonbon.mint_key = key(9);
+        bonbon.transfers = vec![
+            transfer(1, TransferKind::Mint, None, Some((1, 11))),
+            transfer(2, TransferKind::Transfer, Some((1, 11)), Some((2, 12))),
+            transfer(3, TransferKind::Burn, Some((2, 12)), None),
+        ];
+        bonbon.authority_changes = vec![
+            AuthorityChange {
+                kind: AuthorityKind::Mint,
+                new_authority: Some(key(1)),
+                instruction_index: index(1),
+            },
+            AuthorityChange {
+                kind: AuthorityKind::Mint,
+                new_authority: None,
+                instruction_index: index(4),
+            },
+        ];
+        bonbon
+    }
+
+    #[test]
+    fn add_bonbon_nodes() {
+        let mut graph = Graph::default();
+        graph.add_bonbon(&bonbon());
+        let nodes = graph.nodes.values().map(|n| (n.key, n.kind)).collect::<Vec<_>>();
+        assert_eq!(nodes, vec![
+            (key(1), NodeKind::Wallet),
+            (key(2), NodeKind::Wallet),
+            (key(9), NodeKind::Mint),
+            (key(11), NodeKind::TokenAccount),
+            (key(12), NodeKind::TokenAccount),
+        ]);
+    }
+
+    #[test]
+    fn add_bonbon_edges() {
+        let mut graph = Graph::default();
+        graph.add_bonbon(&bonbon());
+        let edges = graph.edges.iter()
+            .map(|e| (e.source, e.target, e.kind, e.instruction_index.slot))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![
+            (key(1), key(11), EdgeKind::Holds, 1),
+            (key(9), key(1), EdgeKind::Transfer(TransferKind::Mint), 1),
+            (key(2), key(12), EdgeKind::Holds, 2),
+            (key(1), key(2), EdgeKind::Transfer(TransferKind::Transfer), 2),
+            (key(2), key(9), EdgeKind::Transfer(TransferKind::Burn), 3),
+            (key(9), key(1), EdgeKind::Authority(AuthorityKind::Mint), 1),
+            (key(1), key(9), EdgeKind::Authority(AuthorityKind::Mint), 4),
+        ]);
+    }
+
+    #[test]
+    fn add_bonbon_shares_nodes_and_holds_between_bonbons() {
+        let mut graph = Graph::default();
+        graph.add_bonbon(&bonbon());
+        graph.add_bonbon(&bonbon());
+        assert_eq!(graph.nodes.len(), 5);
+        let holds = graph.edges.iter().filter(|e| e.kind == EdgeKind::Holds).count();
+        assert_eq!(holds, 2);
+    }
+
+    // buyer is the fee payer
+    fn sale(buyer: (u64, u64), seller: (u64, u64), fee: u64) -> TransactionWithStatusMeta {
+        TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![],
+                message: VersionedMessage::Legacy(Message {
+                    account_keys: vec![key(2), key(1), key(7)],
+                    ..Message::default()
+                }),
+            },
+            meta: TransactionStatusMeta {
+                fee,
+                pre_balances: vec![buyer.0, seller.0, 0],
+                post_balances: vec![buyer.1, seller.1, 0],
+                ..TransactionStatusMeta::default()
+            },
+        })
+    }
+
+    #[test]
+    fn sale_lamports_leaves_out_the_fee() {
+        let transaction = sale((5_010_000, 5_000), (0, 4_500_000), 5_000);
+        assert_eq!(sale_lamports(&transaction, &key(1), &key(2)), Some(5_000_000));
+    }
+
+    #[test]
+    fn sale_lamports_needs_the_seller_paid() {
+        let transaction = sale((1_000_000, 995_000), (100, 100), 5_000);
+        assert_eq!(sale_lamports(&transaction, &key(1), &key(2)), None);
+        assert_eq!(sale_lamports(&transaction, &key(1), &key(3)), None);
+    }
+
+    #[test]
+    fn add_sale_lamports_prices_wallet_transfers() {
+        let mut graph = Graph::default();
+        graph.add_bonbon(&bonbon());
+        let signature = Signature::new(&[2; 64]);
+        assert_eq!(graph.transfer_signatures(), HashSet::from([signature]));
+
+        let transaction = sale((1_000_000, 495_000), (0, 450_000), 5_000);
+        graph.add_sale_lamports(&HashMap::from([(signature, transaction)]));
+        let prices = graph.edges.iter()
+            .filter_map(|e| Some((e.kind, e.lamports?)))
+            .collect::<Vec<_>>();
+        assert_eq!(prices, vec![(EdgeKind::Transfer(TransferKind::Transfer), 500_000)]);
+    }
+}
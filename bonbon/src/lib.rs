@@ -1,4 +1,7 @@
 pub mod partition;
 pub mod assemble;
 pub mod convert;
+pub mod diff;
+pub mod graph;
+pub mod validate;
 
@@ -145,10 +145,9 @@ pub fn partition_token_instruction(
         TokenInstruction::Revoke => token_account_mint_key(0),
         TokenInstruction::SetAuthority { authority_type, .. } => {
             match authority_type {
-                // TODO: we probably don't care about this case?
-                // might be related to nft mint but shouldn't impact our handling...
+                // account 0 is the mint. we track these to know whether supply is fixed
                 AuthorityType::MintTokens => Ok(Some(*get_account_key(0)?)),
-                AuthorityType::FreezeAccount => Ok(None),
+                AuthorityType::FreezeAccount => Ok(Some(*get_account_key(0)?)),
                 // here we could be changing ownership (aka transfer) so do handle this one...
                 _ => token_account_mint_key(0),
             }
@@ -391,6 +390,114 @@ pub fn meta_from_balance(
     })
 }
 
+// variant name of an instruction for the programs there are partitioners for, e.g
+// `FreezeDelegatedAccount`. None for other programs and data that doesn't deserialize
+pub fn instruction_variant(program_id: &Pubkey, data: &[u8]) -> Option<&'static str> {
+    if program_id == &spl_token::id() {
+        Some(token_instruction_variant(&TokenInstruction::unpack(data).ok()?))
+    } else if program_id == &mpl_token_metadata::id() {
+        Some(metadata_instruction_variant(&MetadataInstruction::try_from_slice(data).ok()?))
+    } else {
+        None
+    }
+}
+
+fn token_instruction_variant(instruction: &TokenInstruction) -> &'static str {
+    match instruction {
+        TokenInstruction::InitializeMint { .. } => "InitializeMint",
+        TokenInstruction::InitializeAccount { .. } => "InitializeAccount",
+        TokenInstruction::InitializeAccount2 { .. } => "InitializeAccount2",
+        TokenInstruction::InitializeMultisig { .. } => "InitializeMultisig",
+        TokenInstruction::Transfer { .. } => "Transfer",
+        TokenInstruction::Approve { .. } => "Approve",
+        TokenInstruction::Revoke { .. } => "Revoke",
+        TokenInstruction::SetAuthority { .. } => "SetAuthority",
+        TokenInstruction::MintTo { .. } => "MintTo",
+        TokenInstruction::Burn { .. } => "Burn",
+        TokenInstruction::CloseAccount { .. } => "CloseAccount",
+        TokenInstruction::FreezeAccount { .. } => "FreezeAccount",
+        TokenInstruction::ThawAccount { .. } => "ThawAccount",
+        TokenInstruction::TransferChecked { .. } => "TransferChecked",
+        TokenInstruction::ApproveChecked { .. } => "ApproveChecked",
+        TokenInstruction::MintToChecked { .. } => "MintToChecked",
+        TokenInstruction::BurnChecked { .. } => "BurnChecked",
+        TokenInstruction::SyncNative { .. } => "SyncNative",
+        TokenInstruction::InitializeAccount3 { .. } => "InitializeAccount3",
+        TokenInstruction::InitializeMultisig2 { .. } => "InitializeMultisig2",
+        TokenInstruction::InitializeMint2 { .. } => "InitializeMint2",
+        TokenInstruction::GetAccountDataSize { .. } => "GetAccountDataSize",
+        TokenInstruction::AmountToUiAmount { .. } => "AmountToUiAmount",
+        TokenInstruction::UiAmountToAmount { .. } => "UiAmountToAmount",
+        TokenInstruction::TransferFeeExtension { .. } => "TransferFeeExtension",
+        TokenInstruction::ConfidentialTransferExtension { .. } => "ConfidentialTransferExtension",
+        TokenInstruction::DefaultAccountStateExtension { .. } => "DefaultAccountStateExtension",
+        TokenInstruction::MemoTransferExtension { .. } => "MemoTransferExtension",
+        TokenInstruction::InterestBearingMintExtension { .. } => "InterestBearingMintExtension",
+        TokenInstruction::Reallocate { .. } => "Reallocate",
+        TokenInstruction::CreateNativeMint { .. } => "CreateNativeMint",
+        TokenInstruction::InitializeImmutableOwner { .. } => "InitializeImmutableOwner",
+        TokenInstruction::InitializeMintCloseAuthority { .. } => "InitializeMintCloseAuthority",
+        TokenInstruction::InitializeNonTransferableMint { .. } => "InitializeNonTransferableMint",
+    }
+}
+
+fn metadata_instruction_variant(instruction: &MetadataInstruction) -> &'static str {
+    match instruction {
+        MetadataInstruction::CreateMetadataAccount { .. } => "CreateMetadataAccount",
+        MetadataInstruction::CreateMetadataAccountV2 { .. } => "CreateMetadataAccountV2",
+        MetadataInstruction::UpdateMetadataAccount { .. } => "UpdateMetadataAccount",
+        MetadataInstruction::UpdateMetadataAccountV2 { .. } => "UpdateMetadataAccountV2",
+        MetadataInstruction::DeprecatedCreateMasterEdition { .. } => {
+            "DeprecatedCreateMasterEdition"
+        }
+        MetadataInstruction::CreateMasterEdition { .. } => "CreateMasterEdition",
+        MetadataInstruction::CreateMasterEditionV3 { .. } => "CreateMasterEditionV3",
+        MetadataInstruction::DeprecatedMintNewEditionFromMasterEditionViaPrintingToken { .. } => {
+            "DeprecatedMintNewEditionFromMasterEditionViaPrintingToken"
+        }
+        MetadataInstruction::MintNewEditionFromMasterEditionViaToken { .. } => {
+            "MintNewEditionFromMasterEditionViaToken"
+        }
+        MetadataInstruction::MintNewEditionFromMasterEditionViaVaultProxy { .. } => {
+            "MintNewEditionFromMasterEditionViaVaultProxy"
+        }
+        MetadataInstruction::SignMetadata { .. } => "SignMetadata",
+        MetadataInstruction::RemoveCreatorVerification { .. } => "RemoveCreatorVerification",
+        MetadataInstruction::VerifyCollection { .. } => "VerifyCollection",
+        MetadataInstruction::SetAndVerifyCollection { .. } => "SetAndVerifyCollection",
+        MetadataInstruction::UnverifyCollection { .. } => "UnverifyCollection",
+        MetadataInstruction::UpdatePrimarySaleHappenedViaToken { .. } => {
+            "UpdatePrimarySaleHappenedViaToken"
+        }
+        MetadataInstruction::DeprecatedSetReservationList { .. } => "DeprecatedSetReservationList",
+        MetadataInstruction::DeprecatedCreateReservationList { .. } => {
+            "DeprecatedCreateReservationList"
+        }
+        MetadataInstruction::DeprecatedMintPrintingTokensViaToken { .. } => {
+            "DeprecatedMintPrintingTokensViaToken"
+        }
+        MetadataInstruction::DeprecatedMintPrintingTokens { .. } => "DeprecatedMintPrintingTokens",
+        MetadataInstruction::ConvertMasterEditionV1ToV2 { .. } => "ConvertMasterEditionV1ToV2",
+        MetadataInstruction::PuffMetadata { .. } => "PuffMetadata",
+        MetadataInstruction::Utilize { .. } => "Utilize",
+        MetadataInstruction::ApproveUseAuthority { .. } => "ApproveUseAuthority",
+        MetadataInstruction::RevokeUseAuthority { .. } => "RevokeUseAuthority",
+        MetadataInstruction::ApproveCollectionAuthority { .. } => "ApproveCollectionAuthority",
+        MetadataInstruction::RevokeCollectionAuthority { .. } => "RevokeCollectionAuthority",
+        MetadataInstruction::FreezeDelegatedAccount { .. } => "FreezeDelegatedAccount",
+        MetadataInstruction::ThawDelegatedAccount { .. } => "ThawDelegatedAccount",
+        MetadataInstruction::BurnNft { .. } => "BurnNft",
+        MetadataInstruction::VerifySizedCollectionItem { .. } => "VerifySizedCollectionItem",
+        MetadataInstruction::UnverifySizedCollectionItem { .. } => "UnverifySizedCollectionItem",
+        MetadataInstruction::SetAndVerifySizedCollectionItem { .. } => {
+            "SetAndVerifySizedCollectionItem"
+        }
+        MetadataInstruction::CreateMetadataAccountV3 { .. } => "CreateMetadataAccountV3",
+        MetadataInstruction::SetCollectionSize { .. } => "SetCollectionSize",
+        MetadataInstruction::SetTokenStandard { .. } => "SetTokenStandard",
+    }
+}
+
 #[derive(Debug)]
 pub enum Reason {
     PartitionFailure {
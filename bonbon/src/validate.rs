@@ -0,0 +1,278 @@
+use {
+    crate::assemble::{
+        Bonbon, EditionStatus, Glazing, GlazingSource, InstructionIndex, TransferKind,
+    },
+    solana_sdk::pubkey::Pubkey,
+};
+
+#[cfg(feature = "serde-feature")]
+use {
+    serde_with::{As, DisplayFromStr},
+    serde::{Deserialize, Serialize},
+};
+
+// things that shouldn't happen to a well-formed NFT. these are either data problems on-chain or
+// (more likely) gaps in how we assemble, so they're reported rather than treated as errors
+#[cfg_attr(feature = "serde-feature", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum Anomaly {
+    // metadata lists creators but their shares don't add up to 100
+    CreatorSharesNotHundred {
+        total: i64,
+
+        instruction_index: InstructionIndex,
+    },
+
+    // a creator signed (or unsigned) the metadata without being in its creator list. when there's
+    // no metadata yet, `apply_creator_verification` makes up a creator with share 0
+    UnlistedCreatorVerification {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        creator_key: Pubkey,
+
+        instruction_index: InstructionIndex,
+    },
+
+    // the token was given an owner again after being burned
+    TransferAfterBurn {
+        instruction_index: InstructionIndex,
+    },
+
+    // the token was minted but someone can still mint more
+    MintAuthorityRetained {
+        #[cfg_attr(
+            feature = "serde-feature",
+            serde(with = "As::<DisplayFromStr>")
+        )]
+        mint_authority: Pubkey,
+    },
+
+    MultipleMints {
+        count: usize,
+
+        // the first extra mint
+        instruction_index: InstructionIndex,
+    },
+}
+
+pub fn validate(bonbon: &Bonbon) -> Vec<Anomaly> {
+    let mut anomalies = vec![];
+
+    let mut prev_glazing: Option<&Glazing> = None;
+    for glazing in &bonbon.glazings {
+        match &glazing.source {
+            GlazingSource::Data | GlazingSource::MasterEdition => {
+                if !glazing.creators.is_empty() {
+                    let total = glazing.creators.iter().map(|c| i64::from(c.share)).sum::<i64>();
+                    if total != 100 {
+                        anomalies.push(Anomaly::CreatorSharesNotHundred {
+                            total,
+                            instruction_index: glazing.instruction_index.clone(),
+                        });
+                    }
+                }
+            }
+            GlazingSource::CreatorVerification { creator_key, .. } => {
+                // verification copies the previous creator list so check against that
+                let listed = prev_glazing
+                    .map(|p| p.creators.iter().any(|c| &c.address == creator_key))
+                    .unwrap_or(false);
+                if !listed {
+                    anomalies.push(Anomaly::UnlistedCreatorVerification {
+                        creator_key: *creator_key,
+                        instruction_index: glazing.instruction_index.clone(),
+                    });
+                }
+            }
+            GlazingSource::CollectionVerification { .. } => {}
+        }
+        prev_glazing = Some(glazing);
+    }
+
+    let mut burned = false;
+    let mut mints = vec![];
+    for transfer in &bonbon.transfers {
+        match transfer.kind {
+            TransferKind::Burn | TransferKind::BurnNft => {
+                burned = true;
+            }
+            TransferKind::Mint => {
+                mints.push(transfer);
+            }
+            _ => {}
+        }
+
+        // BurnNft follows the inner token Burn so only complain about actually getting an owner
+        if burned && transfer.end.is_some() {
+            anomalies.push(Anomaly::TransferAfterBurn {
+                instruction_index: transfer.instruction_index.clone(),
+            });
+        }
+    }
+
+    if mints.len() > 1 {
+        anomalies.push(Anomaly::MultipleMints {
+            count: mints.len(),
+            instruction_index: mints[1].instruction_index.clone(),
+        });
+    }
+
+    // master editions move the mint authority to the edition account which is fine
+    if let Some(mint_authority) = bonbon.mint_authority {
+        if !mints.is_empty() && bonbon.edition_status == EditionStatus::None {
+            anomalies.push(Anomaly::MintAuthorityRetained { mint_authority });
+        }
+    }
+
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::assemble::{Creator, Ownership, Transfer},
+        solana_sdk::signature::Signature,
+    };
+
+    fn key(n: u8) -> Pubkey {
+        Pubkey::new_from_array([n; 32])
+    }
+
+    fn index(slot: i64) -> InstructionIndex {
+        InstructionIndex { slot, block_index: 0, outer_index: 0, inner_index: None }
+    }
+
+    fn creator(n: u8, share: i16) -> Creator {
+        Creator { address: key(n), verified: false, share }
+    }
+
+    fn data_glazing(slot: i64, creators: Vec<Creator>) -> Glazing {
+        Glazing {
+            creators,
+            instruction_index: index(slot),
+            source: GlazingSource::Data,
+            ..Glazing::default()
+        }
+    }
+
+    fn verification_glazing(slot: i64, creator_key: Pubkey, creators: Vec<Creator>) -> Glazing {
+        Glazing {
+            creators,
+            instruction_index: index(slot),
+            source: GlazingSource::CreatorVerification { creator_key, verified: true },
+            ..Glazing::default()
+        }
+    }
+
+    fn transfer(slot: i64, kind: TransferKind, owner: Option<u8>) -> Transfer {
+        Transfer {
+            instruction_index: index(slot),
+            signature: Signature::default(),
+            kind,
+            authority: key(0),
+            delegated: false,
+            start: None,
+            end: owner.map(|n| Ownership { owner: key(n), account: key(n + 100) }),
+        }
+    }
+
+    // a master edition minted once to wallet 1 with creators 1 and 2, verified by creator 1
+    fn clean() -> Bonbon {
+        let mut bonbon = Bonbon::default();
+        bonbon.edition_status = EditionStatus::Master;
+        bonbon.glazings = vec![
+            data_glazing(1, vec![creator(1, 60), creator(2, 40)]),
+            verification_glazing(2, key(1), vec![creator(1, 60), creator(2, 40)]),
+        ];
+        bonbon.transfers = vec![transfer(1, TransferKind::Mint, Some(1))];
+        bonbon
+    }
+
+    #[test]
+    fn clean_bonbon() {
+        assert!(validate(&clean()).is_empty());
+    }
+
+    #[test]
+    fn creator_shares_not_hundred() {
+        let mut bonbon = clean();
+        bonbon.glazings = vec![data_glazing(3, vec![creator(1, 60), creator(2, 30)])];
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::CreatorSharesNotHundred { total: 90, instruction_index }]
+                if instruction_index.slot == 3
+        ), "{:?}", anomalies);
+    }
+
+    #[test]
+    fn unlisted_creator_verification() {
+        let mut bonbon = clean();
+        bonbon.glazings.push(verification_glazing(3, key(5), vec![]));
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::UnlistedCreatorVerification { creator_key, instruction_index }]
+                if *creator_key == key(5) && instruction_index.slot == 3
+        ), "{:?}", anomalies);
+    }
+
+    #[test]
+    fn verification_before_any_data_is_unlisted() {
+        let mut bonbon = clean();
+        bonbon.glazings = vec![verification_glazing(1, key(1), vec![creator(1, 0)])];
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::UnlistedCreatorVerification { .. }]
+        ), "{:?}", anomalies);
+    }
+
+    #[test]
+    fn transfer_after_burn() {
+        let mut bonbon = clean();
+        bonbon.transfers.push(transfer(2, TransferKind::Burn, None));
+        // the BurnNft after the inner Burn doesn't count
+        bonbon.transfers.push(transfer(2, TransferKind::BurnNft, None));
+        bonbon.transfers.push(transfer(3, TransferKind::Transfer, Some(2)));
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::TransferAfterBurn { instruction_index }] if instruction_index.slot == 3
+        ), "{:?}", anomalies);
+    }
+
+    #[test]
+    fn mint_authority_retained() {
+        let mut bonbon = clean();
+        bonbon.edition_status = EditionStatus::None;
+        bonbon.mint_authority = Some(key(1));
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::MintAuthorityRetained { mint_authority }] if *mint_authority == key(1)
+        ), "{:?}", anomalies);
+    }
+
+    #[test]
+    fn master_edition_holds_the_mint_authority() {
+        let mut bonbon = clean();
+        bonbon.mint_authority = Some(key(9));
+        assert!(validate(&bonbon).is_empty());
+    }
+
+    #[test]
+    fn multiple_mints() {
+        let mut bonbon = clean();
+        bonbon.transfers.push(transfer(4, TransferKind::Mint, Some(2)));
+        bonbon.transfers.push(transfer(5, TransferKind::Mint, Some(3)));
+        let anomalies = validate(&bonbon);
+        assert!(matches!(
+            anomalies.as_slice(),
+            [Anomaly::MultipleMints { count: 3, instruction_index }] if instruction_index.slot == 4
+        ), "{:?}", anomalies);
+    }
+}
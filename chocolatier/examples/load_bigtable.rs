@@ -0,0 +1,79 @@
+// uploads a directory of recorded blocks (see `fetch --source json|protobuf`) into bigtable so
+// `fetch` can be run against it. meant for the emulator, see scripts/bigtable_emulator_test.sh
+use {
+    anyhow::{Result, anyhow},
+    chocolatier::ledger::{self, LedgerSource},
+    solana_transaction_status::VersionedConfirmedBlock,
+};
+
+fn main() -> Result<()> {
+    let matches = clap::Command::new("load_bigtable")
+        .arg(
+            clap::Arg::new("blocks_dir")
+                .long("blocks_dir")
+                .value_name("DIRPATH")
+                .takes_value(true)
+                .required(true)
+                .help("Directory of per-slot block dumps")
+        )
+        .arg(
+            clap::Arg::new("format")
+                .long("format")
+                .value_name("json|protobuf")
+                .takes_value(true)
+                .default_value("json")
+                .help("Format of the block dumps")
+        )
+        .arg(
+            clap::Arg::new("emulator_host")
+                .long("emulator_host")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .required(true)
+                .help("Bigtable emulator to load into")
+        )
+        .arg(
+            clap::Arg::new("instance")
+                .long("instance")
+                .value_name("INSTANCE_NAME")
+                .takes_value(true)
+                .help("Bigtable instance [default: solana-ledger]")
+        )
+        .get_matches();
+
+    ledger::set_emulator_env(matches.value_of("emulator_host").unwrap());
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(load(&matches))
+}
+
+async fn load(matches: &clap::ArgMatches) -> Result<()> {
+    let format = match matches.value_of("format").unwrap() {
+        "json" => ledger::DumpFormat::Json,
+        "protobuf" => ledger::DumpFormat::Protobuf,
+        other => return Err(anyhow!("Unknown --format {}", other)),
+    };
+    let source = ledger::DumpSource::new(
+        std::path::Path::new(matches.value_of("blocks_dir").unwrap()), format)?;
+
+    let mut config = ledger::BigTableConfig {
+        emulator_host: matches.value_of("emulator_host").map(|h| h.to_string()),
+        ..ledger::BigTableConfig::default()
+    };
+    if let Some(instance_name) = matches.value_of("instance") {
+        config.instance_name = instance_name.to_string();
+    }
+    let bt = config.connect(false).await?;
+
+    let slots = source.get_confirmed_blocks(0, usize::MAX).await?;
+    for (slot, block) in source.get_blocks(&slots).await? {
+        let block = VersionedConfirmedBlock::try_from(block)
+            .map_err(|e| anyhow!("slot {}: {:?}", slot, e))?;
+        bt.upload_confirmed_block(slot, block).await?;
+    }
+    println!("loaded {} blocks", slots.len());
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use {
+    crate::convert,
+    anyhow::Result,
+    solana_sdk::pubkey::Pubkey,
+};
+
+#[derive(Debug)]
+pub struct ActivityRecord {
+    pub instruction_index: convert::InstructionIndex,
+
+    pub kind: String,
+
+    pub mint_key: String,
+
+    pub counterparty: Option<String>,
+
+    pub signature: String,
+}
+
+// a page of `wallet`'s activity in chain order, starting after `after`. pass the last record's
+// instruction_index to get the next page
+pub fn activities(
+    psql_client: &mut postgres::Client,
+    wallet: &Pubkey,
+    after: Option<convert::InstructionIndex>,
+    limit: i64,
+) -> Result<Vec<ActivityRecord>> {
+    let after = after.unwrap_or(convert::InstructionIndex {
+        slot: -1,
+        block_index: -1,
+        outer_index: -1,
+        inner_index: None,
+    });
+    Ok(psql_client
+        .query(
+            "SELECT instruction_index, kind::VARCHAR, mint_key, counterparty, signature
+             FROM activities
+             WHERE wallet = $1 AND instruction_index > $2
+             ORDER BY instruction_index
+             LIMIT $3
+            ",
+            &[&wallet.to_string(), &after, &limit],
+        )?
+        .into_iter()
+        .map(|row| ActivityRecord {
+            instruction_index: row.get(0),
+            kind: row.get(1),
+            mint_key: row.get(2),
+            counterparty: row.get(3),
+            signature: row.get(4),
+        })
+        .collect())
+}
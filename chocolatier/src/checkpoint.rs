@@ -0,0 +1,196 @@
+use {
+    anyhow::Result,
+    solana_sdk::clock::Slot,
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+// inclusive slot ranges, kept sorted with overlapping and adjacent ranges merged
+#[derive(Debug, Default, Clone)]
+pub struct SlotRanges {
+    // start -> end
+    ranges: BTreeMap<Slot, Slot>,
+}
+
+impl SlotRanges {
+    pub fn insert(&mut self, start: Slot, end: Slot) {
+        let (mut start, mut end) = (start, end);
+        let touching = self.ranges
+            .range(..=end.saturating_add(1))
+            .filter(|(_, e)| e.saturating_add(1) >= start)
+            .map(|(s, _)| *s)
+            .collect::<Vec<_>>();
+        for s in touching {
+            let e = self.ranges.remove(&s).unwrap();
+            start = std::cmp::min(start, s);
+            end = std::cmp::max(end, e);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    // first slot at or after `slot` that isn't in a range
+    pub fn next_uncovered(&self, slot: Slot) -> Slot {
+        match self.ranges.range(..=slot).next_back() {
+            Some((_, e)) if *e >= slot => e + 1,
+            _ => slot,
+        }
+    }
+
+    // start of the first range after `slot`
+    pub fn next_covered(&self, slot: Slot) -> Option<Slot> {
+        self.ranges.range(slot..).next().map(|(s, _)| *s)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Slot, Slot)> + '_ {
+        self.ranges.iter().map(|(s, e)| (*s, *e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, slot: Slot) -> bool {
+        self.next_uncovered(slot) != slot
+    }
+
+    // ranges within [start, end] that aren't covered
+    pub fn gaps(&self, start: Slot, end: Slot) -> Vec<(Slot, Slot)> {
+        let mut gaps = vec![];
+        let mut slot = self.next_uncovered(start);
+        while slot <= end {
+            let gap_end = match self.next_covered(slot) {
+                Some(covered_start) if covered_start <= end => covered_start - 1,
+                _ => end,
+            };
+            gaps.push((slot, gap_end));
+            if gap_end == Slot::MAX {
+                break;
+            }
+            slot = self.next_uncovered(gap_end + 1);
+        }
+        gaps
+    }
+}
+
+// which table a checkpointed range's rows went to. fetch writes transactions, ingest writes
+// partitions and optionally transactions too
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Transactions,
+    Partitions,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Transactions => "transactions",
+            Stage::Partitions => "partitions",
+        }
+    }
+}
+
+// what fetch saw in the slots it covered. every fetched slot that isn't skipped or failed was a
+// block
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    pub fetched: SlotRanges,
+
+    // no block in the ledger
+    pub skipped: BTreeSet<Slot>,
+
+    // listed as a block but the source didn't return it
+    pub failed: BTreeSet<Slot>,
+}
+
+impl Coverage {
+    pub fn add(&mut self, first_slot: Slot, last_slot: Slot, skipped: &[Slot], failed: &[Slot]) {
+        self.fetched.insert(first_slot, last_slot);
+        self.skipped.extend(skipped);
+        self.failed.extend(failed);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fetched.is_empty()
+    }
+
+    // fetched slots that don't need fetching again
+    pub fn completed(&self) -> SlotRanges {
+        let mut completed = SlotRanges::default();
+        for (start, end) in self.fetched.iter() {
+            let mut from = start;
+            for failed in self.failed.range(start..=end) {
+                if *failed > from {
+                    completed.insert(from, failed - 1);
+                }
+                from = failed + 1;
+            }
+            if from <= end {
+                completed.insert(from, end);
+            }
+        }
+        completed
+    }
+}
+
+// what fetch has recorded for `stage` with `filter` for slots overlapping [start, end]. a slot that failed
+// once but was fetched by a later run isn't failed anymore. older checkpoints stored the filter's
+// slot bounds too, which aren't part of its identity
+pub fn load(
+    psql_client: &mut postgres::Client,
+    start: Slot,
+    end: Slot,
+    filter: &serde_json::Value,
+    stage: Stage,
+) -> Result<Coverage> {
+    let mut coverage = Coverage::default();
+    let mut fetched_ok = SlotRanges::default();
+    for row in psql_client.query(
+        "SELECT start_slot, end_slot, skipped, failed
+         FROM fetch_checkpoints
+         WHERE start_slot <= $2 AND end_slot >= $1
+           AND filter - '{min_slot,max_slot}'::text[] = $3 AND stage = $4
+        ",
+        &[&(start as i64), &(end as i64), filter, &stage.name()],
+    )? {
+        let (start_slot, end_slot): (i64, i64) = (row.get(0), row.get(1));
+        let skipped = row.get::<_, Vec<i64>>(2).into_iter().map(|s| s as Slot).collect::<Vec<_>>();
+        let failed = row.get::<_, Vec<i64>>(3).into_iter().map(|s| s as Slot).collect::<Vec<_>>();
+
+        let mut row_coverage = Coverage::default();
+        row_coverage.add(start_slot as Slot, end_slot as Slot, &skipped, &failed);
+        for (s, e) in row_coverage.completed().iter() {
+            fetched_ok.insert(s, e);
+        }
+        coverage.add(start_slot as Slot, end_slot as Slot, &skipped, &failed);
+    }
+    coverage.failed.retain(|s| !fetched_ok.contains(*s));
+    Ok(coverage)
+}
+
+// must be called in the same DB transaction as the rows for the ranges
+pub fn record(
+    db_transaction: &mut postgres::Transaction,
+    coverage: &Coverage,
+    filter: &serde_json::Value,
+    stage: Stage,
+) -> Result<()> {
+    let within = |slots: &BTreeSet<Slot>, start: Slot, end: Slot| slots
+        .range(start..=end)
+        .map(|s| *s as i64)
+        .collect::<Vec<_>>();
+    for (start, end) in coverage.fetched.iter() {
+        db_transaction.execute(
+            "INSERT INTO fetch_checkpoints
+               (start_slot, end_slot, completed_at, filter, skipped, failed, stage)
+             VALUES ($1, $2, now(), $3, $4, $5, $6)",
+            &[
+                &(start as i64),
+                &(end as i64),
+                filter,
+                &within(&coverage.skipped, start, end),
+                &within(&coverage.failed, start, end),
+                &stage.name(),
+            ],
+        )?;
+    }
+    Ok(())
+}
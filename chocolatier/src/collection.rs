@@ -0,0 +1,201 @@
+use {
+    anyhow::Result,
+    bonbon::assemble::{Bonbon, TransferKind},
+    log::*,
+    std::collections::HashSet,
+};
+
+#[derive(Debug)]
+pub struct CollectionAggregate {
+    pub collection_key: String,
+
+    pub verified_members: i64,
+
+    pub unverified_claimants: i64,
+
+    // the rest are over verified members only
+    pub unique_holders: i64,
+
+    // number of bonbons held -> number of holders holding that many
+    pub holder_distribution: serde_json::Value,
+
+    pub total_burns: i64,
+
+    pub first_mint_slot: Option<i64>,
+
+    pub last_mint_slot: Option<i64>,
+}
+
+// keeps `collection_members` up to date per bonbon and recomputes the aggregate for every
+// collection that gained or lost a member on `flush`. only the members table is read back so
+// collection pages never need to scan glazings
+pub struct CollectionIndex {
+    select_member_statement: postgres::Statement,
+
+    upsert_member_statement: postgres::Statement,
+
+    delete_member_statement: postgres::Statement,
+
+    upsert_aggregate_statement: postgres::Statement,
+
+    delete_aggregate_statement: postgres::Statement,
+
+    dirty: HashSet<String>,
+}
+
+impl CollectionIndex {
+    pub fn new(psql_client: &mut postgres::Client) -> Result<Self> {
+        Ok(Self {
+            select_member_statement: psql_client.prepare(
+                "SELECT collection_key FROM collection_members WHERE mint_key = $1"
+            )?,
+            upsert_member_statement: psql_client.prepare(
+                "INSERT INTO collection_members VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (mint_key) DO UPDATE SET
+                    collection_key = EXCLUDED.collection_key,
+                    verified = EXCLUDED.verified,
+                    owner = EXCLUDED.owner,
+                    burned = EXCLUDED.burned,
+                    mint_slot = EXCLUDED.mint_slot
+                ",
+            )?,
+            delete_member_statement: psql_client.prepare(
+                "DELETE FROM collection_members WHERE mint_key = $1"
+            )?,
+            upsert_aggregate_statement: psql_client.prepare(
+                "INSERT INTO collections
+                 SELECT $1::VARCHAR,
+                        count(*) FILTER (WHERE verified),
+                        count(*) FILTER (WHERE NOT verified),
+                        count(DISTINCT owner) FILTER (WHERE verified AND NOT burned),
+                        (SELECT coalesce(jsonb_object_agg(held, holders), '{}'::jsonb)
+                         FROM (
+                            SELECT held::VARCHAR, count(*) AS holders
+                            FROM (
+                                SELECT count(*) AS held
+                                FROM collection_members
+                                WHERE collection_key = $1 AND verified AND NOT burned
+                                GROUP BY owner
+                            ) per_owner
+                            GROUP BY held
+                         ) distribution),
+                        count(*) FILTER (WHERE verified AND burned),
+                        min(mint_slot) FILTER (WHERE verified),
+                        max(mint_slot) FILTER (WHERE verified),
+                        now()
+                 FROM collection_members
+                 WHERE collection_key = $1
+                 ON CONFLICT (collection_key) DO UPDATE SET
+                    verified_members = EXCLUDED.verified_members,
+                    unverified_claimants = EXCLUDED.unverified_claimants,
+                    unique_holders = EXCLUDED.unique_holders,
+                    holder_distribution = EXCLUDED.holder_distribution,
+                    total_burns = EXCLUDED.total_burns,
+                    first_mint_slot = EXCLUDED.first_mint_slot,
+                    last_mint_slot = EXCLUDED.last_mint_slot,
+                    updated_at = EXCLUDED.updated_at
+                ",
+            )?,
+            delete_aggregate_statement: psql_client.prepare(
+                "DELETE FROM collections c
+                 WHERE collection_key = $1
+                   AND NOT EXISTS (
+                      SELECT 1 FROM collection_members m WHERE m.collection_key = c.collection_key)
+                ",
+            )?,
+            dirty: HashSet::new(),
+        })
+    }
+
+    pub fn update(&mut self, psql_client: &mut postgres::Client, bonbon: &Bonbon) -> Result<()> {
+        let mint_key = bonbon.mint_key.to_string();
+
+        if let Some(row) = psql_client.query_opt(&self.select_member_statement, &[&mint_key])? {
+            self.dirty.insert(row.get(0));
+        }
+
+        match bonbon.collection() {
+            Some(collection) => {
+                let collection_key = collection.address.to_string();
+                let burned = bonbon.current_owner.is_none() && bonbon.transfers.iter()
+                    .any(|t| matches!(t.kind, TransferKind::Burn | TransferKind::BurnNft));
+                let mint_slot = bonbon.transfers.iter()
+                    .find(|t| t.kind == TransferKind::Mint)
+                    .map(|t| t.instruction_index.slot);
+                psql_client.query(
+                    &self.upsert_member_statement,
+                    &[
+                        &mint_key,
+                        &collection_key,
+                        &collection.verified,
+                        &bonbon.current_owner.as_ref().map(|o| o.owner.to_string()),
+                        &burned,
+                        &mint_slot,
+                    ],
+                )?;
+                self.dirty.insert(collection_key);
+            }
+            None => {
+                psql_client.query(&self.delete_member_statement, &[&mint_key])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // recompute aggregates for the collections touched since the last flush
+    pub fn flush(&mut self, psql_client: &mut postgres::Client) -> Result<()> {
+        let flush_start = std::time::Instant::now();
+        let count = self.dirty.len();
+        for collection_key in self.dirty.drain() {
+            psql_client.query(&self.upsert_aggregate_statement, &[&collection_key])?;
+            psql_client.query(&self.delete_aggregate_statement, &[&collection_key])?;
+        }
+        info!("updated {} collections in {:?}", count, flush_start.elapsed());
+        Ok(())
+    }
+}
+
+pub fn aggregate(
+    psql_client: &mut postgres::Client,
+    collection_key: &str,
+) -> Result<Option<CollectionAggregate>> {
+    Ok(psql_client
+        .query_opt(
+            "SELECT collection_key, verified_members, unverified_claimants, unique_holders,
+                    holder_distribution, total_burns, first_mint_slot, last_mint_slot
+             FROM collections
+             WHERE collection_key = $1
+            ",
+            &[&collection_key],
+        )?
+        .map(|row| CollectionAggregate {
+            collection_key: row.get(0),
+            verified_members: row.get(1),
+            unverified_claimants: row.get(2),
+            unique_holders: row.get(3),
+            holder_distribution: row.get(4),
+            total_burns: row.get(5),
+            first_mint_slot: row.get(6),
+            last_mint_slot: row.get(7),
+        }))
+}
+
+// verified members, oldest mint first
+pub fn members(
+    psql_client: &mut postgres::Client,
+    collection_key: &str,
+) -> Result<Vec<String>> {
+    Ok(psql_client
+        .query(
+            "SELECT mint_key
+             FROM collection_members
+             WHERE collection_key = $1 AND verified
+             ORDER BY mint_slot
+            ",
+            &[&collection_key],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect())
+}
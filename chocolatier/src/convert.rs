@@ -1,6 +1,8 @@
 use {
     bonbon::assemble as bb,
+    bonbon::diff as bd,
     bonbon::partition as bp,
+    bonbon::validate as bv,
     postgres_types::*,
     solana_sdk::pubkey::Pubkey,
 };
@@ -32,6 +34,41 @@ impl From<bb::EditionStatus> for EditionStatus {
     }
 }
 
+#[derive(Debug, ToSql)]
+#[postgres(name = "transfer_kind")]
+pub enum TransferKind {
+    #[postgres(name = "mint")]
+    Mint,
+
+    #[postgres(name = "transfer")]
+    Transfer,
+
+    #[postgres(name = "transfer_checked")]
+    TransferChecked,
+
+    #[postgres(name = "owner_change")]
+    OwnerChange,
+
+    #[postgres(name = "burn")]
+    Burn,
+
+    #[postgres(name = "burn_nft")]
+    BurnNft,
+}
+
+impl From<bb::TransferKind> for TransferKind {
+    fn from(k: bb::TransferKind) -> Self {
+        match k {
+            bb::TransferKind::Mint => Self::Mint,
+            bb::TransferKind::Transfer => Self::Transfer,
+            bb::TransferKind::TransferChecked => Self::TransferChecked,
+            bb::TransferKind::OwnerChange => Self::OwnerChange,
+            bb::TransferKind::Burn => Self::Burn,
+            bb::TransferKind::BurnNft => Self::BurnNft,
+        }
+    }
+}
+
 
 #[derive(Debug)]
 pub struct SqlPubkey(pub Pubkey);
@@ -127,7 +164,100 @@ impl From<&bb::Creator> for Creator {
     }
 }
 
-#[derive(Debug, ToSql, FromSql)]
+#[derive(Debug, ToSql)]
+#[postgres(name = "activity_kind")]
+pub enum ActivityKind {
+    #[postgres(name = "mint")]
+    Mint,
+
+    #[postgres(name = "receive")]
+    Receive,
+
+    #[postgres(name = "send")]
+    Send,
+
+    #[postgres(name = "burn")]
+    Burn,
+
+    #[postgres(name = "delegate")]
+    Delegate,
+
+    #[postgres(name = "revoke")]
+    Revoke,
+
+    #[postgres(name = "metadata_update")]
+    MetadataUpdate,
+
+    #[postgres(name = "creator_verify")]
+    CreatorVerify,
+
+    #[postgres(name = "creator_unverify")]
+    CreatorUnverify,
+}
+
+impl From<bb::ActivityKind> for ActivityKind {
+    fn from(k: bb::ActivityKind) -> Self {
+        match k {
+            bb::ActivityKind::Mint => Self::Mint,
+            bb::ActivityKind::Receive => Self::Receive,
+            bb::ActivityKind::Send => Self::Send,
+            bb::ActivityKind::Burn => Self::Burn,
+            bb::ActivityKind::Delegate => Self::Delegate,
+            bb::ActivityKind::Revoke => Self::Revoke,
+            bb::ActivityKind::MetadataUpdate => Self::MetadataUpdate,
+            bb::ActivityKind::CreatorVerify => Self::CreatorVerify,
+            bb::ActivityKind::CreatorUnverify => Self::CreatorUnverify,
+        }
+    }
+}
+
+#[derive(Debug, ToSql)]
+#[postgres(name = "authority_kind")]
+pub enum AuthorityKind {
+    #[postgres(name = "mint")]
+    Mint,
+
+    #[postgres(name = "freeze")]
+    Freeze,
+}
+
+impl From<bb::AuthorityKind> for AuthorityKind {
+    fn from(k: bb::AuthorityKind) -> Self {
+        match k {
+            bb::AuthorityKind::Mint => Self::Mint,
+            bb::AuthorityKind::Freeze => Self::Freeze,
+        }
+    }
+}
+
+#[derive(Debug, ToSql)]
+#[postgres(name = "glazing_source")]
+pub enum GlazingSource {
+    #[postgres(name = "data")]
+    Data,
+
+    #[postgres(name = "master_edition")]
+    MasterEdition,
+
+    #[postgres(name = "creator_verification")]
+    CreatorVerification,
+
+    #[postgres(name = "collection_verification")]
+    CollectionVerification,
+}
+
+impl From<&bb::GlazingSource> for GlazingSource {
+    fn from(s: &bb::GlazingSource) -> Self {
+        match s {
+            bb::GlazingSource::Data => Self::Data,
+            bb::GlazingSource::MasterEdition => Self::MasterEdition,
+            bb::GlazingSource::CreatorVerification { .. } => Self::CreatorVerification,
+            bb::GlazingSource::CollectionVerification { .. } => Self::CollectionVerification,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ToSql, FromSql)]
 #[postgres(name = "instruction_index")]
 pub struct InstructionIndex {
     pub slot: i64,
@@ -150,3 +280,160 @@ impl From<bb::InstructionIndex> for InstructionIndex {
     }
 }
 
+impl From<InstructionIndex> for bb::InstructionIndex {
+    fn from(c: InstructionIndex) -> Self {
+        Self {
+            slot: c.slot,
+            block_index: c.block_index,
+            outer_index: c.outer_index,
+            inner_index: c.inner_index,
+        }
+    }
+}
+
+#[derive(Debug, ToSql)]
+#[postgres(name = "anomaly_kind")]
+pub enum AnomalyKind {
+    #[postgres(name = "creator_shares_not_hundred")]
+    CreatorSharesNotHundred,
+
+    #[postgres(name = "unlisted_creator_verification")]
+    UnlistedCreatorVerification,
+
+    #[postgres(name = "transfer_after_burn")]
+    TransferAfterBurn,
+
+    #[postgres(name = "mint_authority_retained")]
+    MintAuthorityRetained,
+
+    #[postgres(name = "multiple_mints")]
+    MultipleMints,
+}
+
+// flattened for the anomalies table. anything variant-specific goes in `details`
+#[derive(Debug)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+
+    pub instruction_index: Option<InstructionIndex>,
+
+    pub details: Option<String>,
+}
+
+impl From<bv::Anomaly> for Anomaly {
+    fn from(a: bv::Anomaly) -> Self {
+        match a {
+            bv::Anomaly::CreatorSharesNotHundred { total, instruction_index } => Self {
+                kind: AnomalyKind::CreatorSharesNotHundred,
+                instruction_index: Some(instruction_index.into()),
+                details: Some(format!("total share {}", total)),
+            },
+            bv::Anomaly::UnlistedCreatorVerification { creator_key, instruction_index } => Self {
+                kind: AnomalyKind::UnlistedCreatorVerification,
+                instruction_index: Some(instruction_index.into()),
+                details: Some(creator_key.to_string()),
+            },
+            bv::Anomaly::TransferAfterBurn { instruction_index } => Self {
+                kind: AnomalyKind::TransferAfterBurn,
+                instruction_index: Some(instruction_index.into()),
+                details: None,
+            },
+            bv::Anomaly::MintAuthorityRetained { mint_authority } => Self {
+                kind: AnomalyKind::MintAuthorityRetained,
+                instruction_index: None,
+                details: Some(mint_authority.to_string()),
+            },
+            bv::Anomaly::MultipleMints { count, instruction_index } => Self {
+                kind: AnomalyKind::MultipleMints,
+                instruction_index: Some(instruction_index.into()),
+                details: Some(format!("{} mints", count)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, ToSql)]
+#[postgres(name = "glazing_change_kind")]
+pub enum GlazingChangeKind {
+    #[postgres(name = "name")]
+    Name,
+
+    #[postgres(name = "symbol")]
+    Symbol,
+
+    #[postgres(name = "uri")]
+    Uri,
+
+    #[postgres(name = "creator_added")]
+    CreatorAdded,
+
+    #[postgres(name = "creator_removed")]
+    CreatorRemoved,
+
+    #[postgres(name = "creator_share_changed")]
+    CreatorShareChanged,
+
+    #[postgres(name = "creator_verified")]
+    CreatorVerified,
+
+    #[postgres(name = "collection_changed")]
+    CollectionChanged,
+
+    #[postgres(name = "collection_verified")]
+    CollectionVerified,
+
+    #[postgres(name = "royalty_changed")]
+    RoyaltyChanged,
+}
+
+// flattened for the glazing_changes table. `address` is the creator or collection the change is
+// about (if any) and the values are stringified
+#[derive(Debug)]
+pub struct GlazingChange {
+    pub kind: GlazingChangeKind,
+
+    pub address: Option<String>,
+
+    pub old_value: Option<String>,
+
+    pub new_value: Option<String>,
+}
+
+impl From<bd::GlazingChange> for GlazingChange {
+    fn from(c: bd::GlazingChange) -> Self {
+        let build = |kind, address: Option<Pubkey>, old_value: Option<String>, new_value| Self {
+            kind,
+            address: address.map(|a| a.to_string()),
+            old_value,
+            new_value,
+        };
+        match c {
+            bd::GlazingChange::Name { from, to } =>
+                build(GlazingChangeKind::Name, None, Some(from), Some(to)),
+            bd::GlazingChange::Symbol { from, to } =>
+                build(GlazingChangeKind::Symbol, None, Some(from), Some(to)),
+            bd::GlazingChange::Uri { from, to } =>
+                build(GlazingChangeKind::Uri, None, Some(from), Some(to)),
+            bd::GlazingChange::CreatorAdded { address, share, verified } =>
+                build(GlazingChangeKind::CreatorAdded, Some(address), None,
+                      Some(format!("share {} verified {}", share, verified))),
+            bd::GlazingChange::CreatorRemoved { address } =>
+                build(GlazingChangeKind::CreatorRemoved, Some(address), None, None),
+            bd::GlazingChange::CreatorShareChanged { address, from, to } =>
+                build(GlazingChangeKind::CreatorShareChanged, Some(address),
+                      Some(from.to_string()), Some(to.to_string())),
+            bd::GlazingChange::CreatorVerified { address, verified } =>
+                build(GlazingChangeKind::CreatorVerified, Some(address),
+                      Some((!verified).to_string()), Some(verified.to_string())),
+            bd::GlazingChange::CollectionChanged { from, to } =>
+                build(GlazingChangeKind::CollectionChanged, None,
+                      from.map(|k| k.to_string()), to.map(|k| k.to_string())),
+            bd::GlazingChange::CollectionVerified { address, verified } =>
+                build(GlazingChangeKind::CollectionVerified, Some(address),
+                      Some((!verified).to_string()), Some(verified.to_string())),
+            bd::GlazingChange::RoyaltyChanged { from, to } =>
+                build(GlazingChangeKind::RoyaltyChanged, None,
+                      Some(from.to_string()), Some(to.to_string())),
+        }
+    }
+}
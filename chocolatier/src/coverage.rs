@@ -0,0 +1,81 @@
+use {
+    crate::checkpoint,
+    anyhow::Result,
+    solana_sdk::clock::Slot,
+};
+
+// how much of [start, end) fetch (or ingest, for the partitions stage) has covered with a filter,
+// going by its checkpoints alone. the rows aren't checked against them: with a filter most blocks
+// legitimately have no rows, so a block without any isn't a hole
+#[derive(Debug)]
+pub struct Report {
+    pub start: Slot,
+
+    pub end: Slot,
+
+    pub blocks: u64,
+
+    pub skipped: u64,
+
+    // blocks the source listed but never returned
+    pub failed: Vec<Slot>,
+
+    // never fetched with the filter
+    pub unfetched: Vec<(Slot, Slot)>,
+
+    // rows in `transactions` for the range, from any filter. not used to find holes
+    pub transactions: i64,
+}
+
+impl Report {
+    // everything a refill would fetch
+    pub fn holes(&self) -> checkpoint::SlotRanges {
+        let mut holes = checkpoint::SlotRanges::default();
+        for (start, end) in &self.unfetched {
+            holes.insert(*start, *end);
+        }
+        for slot in &self.failed {
+            holes.insert(*slot, *slot);
+        }
+        holes
+    }
+}
+
+pub fn report(
+    psql_client: &mut postgres::Client,
+    start: Slot,
+    end: Slot,
+    filter: &serde_json::Value,
+    stage: checkpoint::Stage,
+) -> Result<Report> {
+    let mut report = Report {
+        start,
+        end,
+        blocks: 0,
+        skipped: 0,
+        failed: vec![],
+        unfetched: vec![],
+        transactions: 0,
+    };
+    if start >= end {
+        return Ok(report);
+    }
+    let last = end - 1;
+
+    let coverage = checkpoint::load(psql_client, start, last, filter, stage)?;
+    let fetched_slots = coverage.fetched
+        .iter()
+        .map(|(s, e)| std::cmp::min(e, last).saturating_sub(std::cmp::max(s, start)) + 1)
+        .sum::<u64>();
+    report.skipped = coverage.skipped.range(start..=last).count() as u64;
+    report.failed = coverage.failed.range(start..=last).cloned().collect();
+    report.blocks = fetched_slots - report.skipped - report.failed.len() as u64;
+    report.unfetched = coverage.fetched.gaps(start, last);
+
+    report.transactions = psql_client.query_one(
+        "SELECT count(*) FROM transactions WHERE slot >= $1 AND slot < $2",
+        &[&(start as i64), &(end as i64)],
+    )?.get(0);
+
+    Ok(report)
+}
@@ -0,0 +1,326 @@
+use {
+    anyhow::{Result, anyhow},
+    bonbon::{
+        assemble::{AuthorityKind, TransferKind},
+        graph::{Edge, EdgeKind, Graph, NodeKind},
+    },
+    serde_json::{json, Value},
+    std::io::Write,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    GraphMl,
+
+    Dot,
+
+    // https://jsongraphformat.info v2
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "graphml" => Ok(Format::GraphMl),
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            other => Err(anyhow!("Unknown format {}", other)),
+        }
+    }
+}
+
+fn node_kind(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Wallet => "wallet",
+        NodeKind::TokenAccount => "token_account",
+        NodeKind::Mint => "mint",
+    }
+}
+
+fn edge_kind(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Transfer(TransferKind::Mint) => "mint",
+        EdgeKind::Transfer(TransferKind::Transfer) => "transfer",
+        EdgeKind::Transfer(TransferKind::TransferChecked) => "transfer_checked",
+        EdgeKind::Transfer(TransferKind::OwnerChange) => "owner_change",
+        EdgeKind::Transfer(TransferKind::Burn) => "burn",
+        EdgeKind::Transfer(TransferKind::BurnNft) => "burn_nft",
+        EdgeKind::Holds => "holds",
+        EdgeKind::Authority(AuthorityKind::Mint) => "mint_authority",
+        EdgeKind::Authority(AuthorityKind::Freeze) => "freeze_authority",
+    }
+}
+
+// label, value pairs shared by every format
+fn edge_attributes(edge: &Edge) -> Vec<(&'static str, String)> {
+    let mut attributes = vec![
+        ("kind", edge_kind(edge.kind).to_string()),
+        ("mint", edge.mint_key.to_string()),
+        ("slot", edge.instruction_index.slot.to_string()),
+        ("instruction_index", edge.instruction_index.to_string()),
+        ("delegated", edge.delegated.to_string()),
+    ];
+    if let Some(signature) = edge.signature {
+        attributes.push(("signature", signature.to_string()));
+    }
+    if let Some(lamports) = edge.lamports {
+        attributes.push(("lamports", lamports.to_string()));
+    }
+    attributes
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_graphml(graph: &Graph, w: &mut dyn Write) -> Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+    writeln!(w, r#"  <key id="node_kind" for="node" attr.name="kind" attr.type="string"/>"#)?;
+    for (name, ty) in [
+        ("kind", "string"),
+        ("mint", "string"),
+        ("slot", "long"),
+        ("instruction_index", "string"),
+        ("delegated", "boolean"),
+        ("signature", "string"),
+        ("lamports", "long"),
+    ] {
+        writeln!(w, r#"  <key id="{0}" for="edge" attr.name="{0}" attr.type="{1}"/>"#, name, ty)?;
+    }
+    writeln!(w, r#"  <graph id="provenance" edgedefault="directed">"#)?;
+    for node in graph.nodes.values() {
+        writeln!(w, r#"    <node id="{}">"#, node.key)?;
+        writeln!(w, r#"      <data key="node_kind">{}</data>"#, node_kind(node.kind))?;
+        writeln!(w, r#"    </node>"#)?;
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        writeln!(w, r#"    <edge id="e{}" source="{}" target="{}">"#, i, edge.source, edge.target)?;
+        for (name, value) in edge_attributes(edge) {
+            writeln!(w, r#"      <data key="{}">{}</data>"#, name, xml_escape(&value))?;
+        }
+        writeln!(w, r#"    </edge>"#)?;
+    }
+    writeln!(w, r#"  </graph>"#)?;
+    writeln!(w, r#"</graphml>"#)?;
+    Ok(())
+}
+
+fn write_dot(graph: &Graph, w: &mut dyn Write) -> Result<()> {
+    writeln!(w, "digraph provenance {{")?;
+    for node in graph.nodes.values() {
+        let shape = match node.kind {
+            NodeKind::Wallet => "ellipse",
+            NodeKind::TokenAccount => "box",
+            NodeKind::Mint => "diamond",
+        };
+        writeln!(w, "  \"{}\" [kind=\"{}\", shape={}];", node.key, node_kind(node.kind), shape)?;
+    }
+    for edge in &graph.edges {
+        let attributes = edge_attributes(edge);
+        let label = format!("{} @ {}", edge_kind(edge.kind), edge.instruction_index.slot);
+        let attributes = std::iter::once(("label", label))
+            .chain(attributes)
+            .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(w, "  \"{}\" -> \"{}\" [{}];", edge.source, edge.target, attributes)?;
+    }
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+fn write_json(graph: &Graph, w: &mut dyn Write) -> Result<()> {
+    let nodes = graph.nodes
+        .values()
+        .map(|node| (
+            node.key.to_string(),
+            json!({ "metadata": { "kind": node_kind(node.kind) } }),
+        ))
+        .collect::<serde_json::Map<_, _>>();
+    let edges = graph.edges
+        .iter()
+        .map(|edge| json!({
+            "source": edge.source.to_string(),
+            "target": edge.target.to_string(),
+            "relation": edge_kind(edge.kind),
+            "directed": true,
+            "metadata": {
+                "mint": edge.mint_key.to_string(),
+                "slot": edge.instruction_index.slot,
+                "instruction_index": edge.instruction_index.to_string(),
+                "delegated": edge.delegated,
+                "signature": edge.signature.map(|s| s.to_string()),
+                "lamports": edge.lamports,
+            },
+        }))
+        .collect::<Vec<Value>>();
+    serde_json::to_writer_pretty(&mut *w, &json!({
+        "graph": {
+            "id": "provenance",
+            "directed": true,
+            "nodes": nodes,
+            "edges": edges,
+        },
+    }))?;
+    writeln!(w)?;
+    Ok(())
+}
+
+pub fn write(graph: &Graph, format: Format, w: &mut dyn Write) -> Result<()> {
+    match format {
+        Format::GraphMl => write_graphml(graph, w),
+        Format::Dot => write_dot(graph, w),
+        Format::Json => write_json(graph, w),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bonbon::{
+            assemble::{Bonbon, InstructionIndex, Ownership, Transfer},
+            graph::Node,
+        },
+        solana_sdk::{pubkey::Pubkey, signature::Signature},
+    };
+
+    fn key(n: u8) -> Pubkey {
+        Pubkey::new_from_array([n; 32])
+    }
+
+    // wallet 1 sells its token to wallet 2
+    fn graph() -> Graph {
+        let mut bonbon = Bonbon::default();
+        bonbon.mint_key = key(9);
+        bonbon.transfers = vec![Transfer {
+            instruction_index: InstructionIndex {
+                slot: 7,
+                block_index: 1,
+                outer_index: 2,
+                inner_index: None,
+            },
+            signature: Signature::new(&[3; 64]),
+            kind: TransferKind::Transfer,
+            authority: key(1),
+            delegated: false,
+            start: Some(Ownership { owner: key(1), account: key(11) }),
+            end: Some(Ownership { owner: key(2), account: key(12) }),
+        }];
+        let mut graph = Graph::default();
+        graph.add_bonbon(&bonbon);
+        graph.edges.retain(|edge| edge.kind != EdgeKind::Holds);
+        graph.nodes.retain(|_, node| node.kind == NodeKind::Wallet);
+        graph.edges[0].lamports = Some(500);
+        graph
+    }
+
+    fn written(graph: &Graph, format: Format) -> String {
+        let mut out = vec![];
+        write(graph, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn graphml() {
+        let (w1, w2, mint) = (key(1), key(2), key(9));
+        let signature = Signature::new(&[3; 64]);
+        let out = written(&graph(), Format::GraphMl);
+        assert!(out.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml"));
+        assert!(out.contains(
+            r#"<key id="lamports" for="edge" attr.name="lamports" attr.type="long"/>"#));
+        assert!(out.contains(&format!(
+            "    <node id=\"{}\">\n      <data key=\"node_kind\">wallet</data>\n    </node>\n",
+            w1)));
+        assert!(out.contains(&format!(
+            "    <edge id=\"e0\" source=\"{}\" target=\"{}\">\n\
+             \x20     <data key=\"kind\">transfer</data>\n\
+             \x20     <data key=\"mint\">{}</data>\n\
+             \x20     <data key=\"slot\">7</data>\n\
+             \x20     <data key=\"instruction_index\">7.1.2</data>\n\
+             \x20     <data key=\"delegated\">false</data>\n\
+             \x20     <data key=\"signature\">{}</data>\n\
+             \x20     <data key=\"lamports\">500</data>\n\
+             \x20   </edge>\n",
+            w1, w2, mint, signature)));
+        assert!(out.ends_with("  </graph>\n</graphml>\n"));
+    }
+
+    #[test]
+    fn dot() {
+        let (w1, w2, mint) = (key(1), key(2), key(9));
+        let signature = Signature::new(&[3; 64]);
+        assert_eq!(written(&graph(), Format::Dot), format!(
+            "digraph provenance {{\n\
+             \x20 \"{0}\" [kind=\"wallet\", shape=ellipse];\n\
+             \x20 \"{1}\" [kind=\"wallet\", shape=ellipse];\n\
+             \x20 \"{0}\" -> \"{1}\" [label=\"transfer @ 7\", kind=\"transfer\", mint=\"{2}\", \
+             slot=\"7\", instruction_index=\"7.1.2\", delegated=\"false\", signature=\"{3}\", \
+             lamports=\"500\"];\n\
+             }}\n",
+            w1, w2, mint, signature));
+    }
+
+    #[test]
+    fn json() {
+        let out: Value = serde_json::from_str(&written(&graph(), Format::Json)).unwrap();
+        assert_eq!(out, json!({
+            "graph": {
+                "id": "provenance",
+                "directed": true,
+                "nodes": {
+                    key(1).to_string(): { "metadata": { "kind": "wallet" } },
+                    key(2).to_string(): { "metadata": { "kind": "wallet" } },
+                },
+                "edges": [{
+                    "source": key(1).to_string(),
+                    "target": key(2).to_string(),
+                    "relation": "transfer",
+                    "directed": true,
+                    "metadata": {
+                        "mint": key(9).to_string(),
+                        "slot": 7,
+                        "instruction_index": "7.1.2",
+                        "delegated": false,
+                        "signature": Signature::new(&[3; 64]).to_string(),
+                        "lamports": 500,
+                    },
+                }],
+            },
+        }));
+    }
+
+    #[test]
+    fn unpriced_edges_leave_out_lamports() {
+        let mut graph = graph();
+        graph.edges[0].lamports = None;
+        assert!(!written(&graph, Format::GraphMl).contains("<data key=\"lamports\">"));
+        assert!(!written(&graph, Format::Dot).contains("lamports="));
+    }
+
+    #[test]
+    fn xml_escapes() {
+        assert_eq!(xml_escape(r#"<a & "b">"#), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn format_from_str() {
+        assert!(matches!("graphml".parse::<Format>(), Ok(Format::GraphMl)));
+        assert!(matches!("dot".parse::<Format>(), Ok(Format::Dot)));
+        assert!(matches!("json".parse::<Format>(), Ok(Format::Json)));
+        assert!("csv".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn node_kinds() {
+        let mut graph = Graph::default();
+        graph.nodes.insert(key(9), Node { key: key(9), kind: NodeKind::Mint });
+        assert!(written(&graph, Format::Dot).contains("[kind=\"mint\", shape=diamond]"));
+    }
+}
@@ -0,0 +1,465 @@
+use {
+    crate::{checkpoint, filter, ledger::LedgerSource, partition, storage, writer},
+    anyhow::{Result, anyhow},
+    crossbeam_channel::Sender,
+    futures::{stream, StreamExt, TryStreamExt},
+    log::*,
+    solana_sdk::clock::Slot,
+    solana_transaction_status::{ConfirmedBlock, TransactionWithStatusMeta},
+    std::collections::HashSet,
+};
+
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    // slots per get_blocks request
+    pub chunk_size: usize,
+
+    // get_blocks requests in flight at once
+    pub concurrency: usize,
+
+    pub decode_threads: usize,
+
+    // blocks queued for the decoders (and decoded blocks queued for the writer) before the
+    // previous stage waits. rounded to whole chunks
+    pub in_flight: usize,
+
+    // rows per COPY
+    pub batch_size: usize,
+
+    pub filter: filter::FilterSpec,
+
+    // write the raw transactions to `transactions`
+    pub store_transactions: bool,
+
+    // partition transactions as they're fetched, for ingest
+    pub partition: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 16,
+            concurrency: 4,
+            decode_threads: 4,
+            in_flight: 256,
+            batch_size: 10000,
+            filter: filter::FilterSpec::default(),
+            store_transactions: true,
+            partition: false,
+        }
+    }
+}
+
+impl FetchOptions {
+    // the checkpoints that say what these options have already written. when partitioning, the
+    // partitions decide: storing the transactions again is harmless
+    pub fn stage(&self) -> checkpoint::Stage {
+        if self.partition {
+            checkpoint::Stage::Partitions
+        } else {
+            checkpoint::Stage::Transactions
+        }
+    }
+}
+
+// chunks are the unit of work through the pipeline. a chunk's rows are committed together with
+// the checkpoint for its slots so a restart picks up exactly where the last commit left off
+struct Chunk<T> {
+    first_slot: Slot,
+
+    // inclusive. past the last block when the source skipped slots up to the next checkpoint
+    last_slot: Slot,
+
+    // slots in [first_slot, last_slot] the source didn't list as blocks
+    skipped: Vec<Slot>,
+
+    // listed but not returned by get_blocks
+    failed: Vec<Slot>,
+
+    items: Vec<T>,
+}
+
+// a checkpoint is written at least this often even if the batch isn't full, e.g when the filter
+// keeps very little
+const MAX_CHUNKS_PER_COMMIT: usize = 64;
+
+struct TransactionRow {
+    slot: i64,
+
+    block_index: i64,
+
+    signature: Vec<u8>,
+
+    // encoded for `transactions` if they're stored
+    data: Option<Vec<u8>>,
+
+    // kept if they're partitioned
+    transaction: Option<TransactionWithStatusMeta>,
+}
+
+fn decode_block(
+    filter: &filter::Filter,
+    options: &FetchOptions,
+    slot: Slot,
+    block: ConfirmedBlock,
+) -> Result<Vec<TransactionRow>> {
+    let mut rows = vec![];
+    for (index, transaction) in block.transactions.into_iter().enumerate() {
+        if !filter.keep(slot, &transaction) {
+            continue;
+        }
+
+        // TODO: dedup some work in bigtable library?
+        let signature = transaction.transaction_signature().as_ref().to_vec();
+        rows.push(TransactionRow {
+            slot: slot as i64,
+            block_index: index as i64,
+            signature,
+            data: if options.store_transactions {
+                Some(storage::encode_transaction(transaction.clone())?)
+            } else {
+                None
+            },
+            transaction: if options.partition { Some(transaction) } else { None },
+        });
+    }
+    Ok(rows)
+}
+
+// lists chunks of slots in order and keeps up to `concurrency` get_blocks requests going. chunks
+// are handed to the decoders in slot order. slots that are already checkpointed are skipped
+async fn fetch_blocks(
+    source: &dyn LedgerSource,
+    block_start: Slot,
+    block_end: Slot,
+    completed: &checkpoint::SlotRanges,
+    options: &FetchOptions,
+    chunks_sender: Sender<Chunk<(Slot, ConfirmedBlock)>>,
+) -> Result<()> {
+    let chunk_size = options.chunk_size as Slot;
+    let chunks = stream::try_unfold(block_start, |chunk_start| async move {
+        let chunk_start = completed.next_uncovered(chunk_start);
+        if chunk_start >= block_end {
+            return Ok(None);
+        }
+        let limit = std::cmp::min(chunk_size, block_end - chunk_start);
+        let mut chunk_slots = source.get_confirmed_blocks(chunk_start, limit as usize).await?;
+
+        // stop at the next checkpointed range so it isn't fetched twice
+        let last_slot = match completed.next_covered(chunk_start) {
+            Some(covered_start) if chunk_slots.last().map_or(false, |s| *s >= covered_start) => {
+                chunk_slots.retain(|s| *s < covered_start);
+                covered_start - 1
+            }
+            _ => match chunk_slots.last() {
+                Some(last_slot) => *last_slot,
+                // nothing more in the source
+                None => return Ok(None),
+            },
+        };
+        Ok::<_, anyhow::Error>(Some(((chunk_start, last_slot, chunk_slots), last_slot + 1)))
+    });
+
+    let mut chunks = chunks
+        .map_ok(|(first_slot, last_slot, chunk_slots)| async move {
+            let items = source.get_blocks(&chunk_slots).await?;
+            let listed = chunk_slots.iter().cloned().collect::<HashSet<_>>();
+            let returned = items.iter().map(|(slot, _)| *slot).collect::<HashSet<_>>();
+            Ok::<_, anyhow::Error>(Chunk {
+                first_slot,
+                last_slot,
+                skipped: (first_slot..=last_slot).filter(|s| !listed.contains(s)).collect(),
+                failed: chunk_slots.into_iter().filter(|s| !returned.contains(s)).collect(),
+                items,
+            })
+        })
+        .try_buffered(std::cmp::max(options.concurrency, 1));
+
+    let mut fetched = 0;
+    while let Some(chunk) = chunks.try_next().await? {
+        fetched += chunk.items.len();
+        for slot in &chunk.failed {
+            warn!("source listed slot {} but didn't return the block", slot);
+        }
+        // blocking the runtime here is the backpressure from the decoders. requests already
+        // in flight wait with it
+        chunks_sender.send(chunk).map_err(|_| anyhow!("decoders stopped"))?;
+        debug!("fetched {} blocks", fetched);
+    }
+
+    Ok(())
+}
+
+fn join<T>(handle: std::thread::JoinHandle<Result<T>>) -> Result<T> {
+    handle.join().map_err(|_| anyhow!("fetch thread panicked"))?
+}
+
+// rows are copied into a staging table and moved over with ON CONFLICT DO NOTHING so refetching
+// a range never duplicates a transaction. the ones that were actually inserted get partitioned,
+// and so do ones stored before that have no partitions, e.g fetched without partitioning, so a
+// partitions checkpoint means every transaction in its slots went through partitioning. without
+// the staging table there's no telling a new transaction from one ingested before, so their
+// derived rows are deleted and written again. the checkpoints for every chunk in the batch go in
+// the same commit as its rows
+struct TransactionWriter {
+    psql_client: postgres::Client,
+
+    writer: writer::CopyWriter,
+
+    staging_table: Option<writer::TableId>,
+
+    partition_writer: Option<partition::PartitionWriter>,
+
+    // held until commit, when it's known which are new
+    partition_rows: Vec<TransactionRow>,
+
+    batch_size: usize,
+
+    pending: checkpoint::Coverage,
+
+    pending_chunks: usize,
+
+    filter: serde_json::Value,
+
+    written: u64,
+
+    partitioned: u64,
+}
+
+impl TransactionWriter {
+    fn new(psql_config: &str, options: &FetchOptions, filter: serde_json::Value) -> Result<Self> {
+        let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+        let mut writer = writer::CopyWriter::new(options.batch_size);
+        let staging_table = if options.store_transactions {
+            psql_client.batch_execute(
+                "CREATE TEMP TABLE transactions_staging (LIKE transactions) ON COMMIT DELETE ROWS"
+            )?;
+            Some(writer.add_table(&mut psql_client, "transactions_staging")?)
+        } else {
+            None
+        };
+        let partition_writer = if options.partition {
+            Some(partition::PartitionWriter::new(&mut psql_client, options.batch_size)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            psql_client,
+            writer,
+            staging_table,
+            partition_writer,
+            partition_rows: vec![],
+            batch_size: options.batch_size,
+            pending: checkpoint::Coverage::default(),
+            pending_chunks: 0,
+            filter,
+            written: 0,
+            partitioned: 0,
+        })
+    }
+
+    fn write(&mut self, chunk: Chunk<TransactionRow>) -> Result<()> {
+        for row in chunk.items {
+            if let (Some(staging_table), Some(data)) = (self.staging_table, &row.data) {
+                self.writer.write(
+                    staging_table,
+                    &[
+                        &row.slot,
+                        &row.block_index,
+                        &row.signature,
+                        data,
+                    ],
+                )?;
+            }
+            if self.partition_writer.is_some() && row.transaction.is_some() {
+                self.partition_rows.push(row);
+            }
+        }
+        self.pending.add(chunk.first_slot, chunk.last_slot, &chunk.skipped, &chunk.failed);
+        self.pending_chunks += 1;
+
+        if self.writer.is_full() || self.partition_rows.len() >= self.batch_size
+            || self.pending_chunks >= MAX_CHUNKS_PER_COMMIT
+        {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut db_transaction = self.psql_client.transaction()?;
+        let inserted = match self.staging_table {
+            Some(_) => {
+                self.writer.flush_in(&mut db_transaction)?;
+                let inserted = db_transaction
+                    .query(
+                        "INSERT INTO transactions SELECT * FROM transactions_staging
+                         ON CONFLICT (signature) DO NOTHING
+                         RETURNING signature",
+                        &[],
+                    )?
+                    .into_iter()
+                    .map(|row| row.get::<_, Vec<u8>>(0))
+                    .collect::<HashSet<_>>();
+                self.written += inserted.len() as u64;
+                checkpoint::record(
+                    &mut db_transaction, &self.pending, &self.filter,
+                    checkpoint::Stage::Transactions)?;
+                Some(inserted)
+            }
+            None => None,
+        };
+        if let Some(partition_writer) = self.partition_writer.as_mut() {
+            let mut rows = std::mem::take(&mut self.partition_rows);
+            let first_slot = rows.iter().map(|row| row.slot).min();
+            let last_slot = rows.iter().map(|row| row.slot).max();
+            if let (Some(first_slot), Some(last_slot)) = (first_slot, last_slot) {
+                // anything that may have derived rows already has them deleted first so they
+                // aren't duplicated
+                let replaced = match &inserted {
+                    Some(inserted) => {
+                        // stored before, e.g by fetch, but maybe never partitioned
+                        let stored = rows.iter()
+                            .filter(|row| !inserted.contains(&row.signature))
+                            .map(|row| row.signature.clone())
+                            .collect::<Vec<_>>();
+                        let unpartitioned = partition::unpartitioned(
+                            &mut db_transaction, first_slot, last_slot, &stored)?;
+                        rows.retain(|row| inserted.contains(&row.signature)
+                                    || unpartitioned.contains(&row.signature));
+                        unpartitioned.into_iter().collect::<Vec<_>>()
+                    }
+                    None => rows.iter().map(|row| row.signature.clone()).collect::<Vec<_>>(),
+                };
+                if !replaced.is_empty() {
+                    partition::delete_derived_of(
+                        &mut db_transaction, first_slot, last_slot, &replaced)?;
+                }
+            }
+            for row in rows {
+                if let Some(transaction) = row.transaction {
+                    partition_writer.write(row.slot, row.block_index, &row.signature, transaction)?;
+                    self.partitioned += 1;
+                }
+                if partition_writer.is_full() {
+                    partition_writer.flush_in(&mut db_transaction)?;
+                }
+            }
+            partition_writer.flush_in(&mut db_transaction)?;
+            checkpoint::record(
+                &mut db_transaction, &self.pending, &self.filter, checkpoint::Stage::Partitions)?;
+        }
+        db_transaction.commit()?;
+
+        for (start, end) in self.pending.fetched.iter() {
+            debug!("checkpointed slots {}-{}", start, end);
+        }
+        self.pending = checkpoint::Coverage::default();
+        self.pending_chunks = 0;
+        Ok(())
+    }
+}
+
+// fetch -> decode/filter -> write, connected by bounded channels. blocks are fetched on `rt`, the
+// decoders and the writer each get their own threads. safe to rerun over the same range, and
+// after a crash only the uncommitted chunks are fetched again
+pub fn fetch(
+    psql_config: &str,
+    rt: &tokio::runtime::Runtime,
+    source: &dyn LedgerSource,
+    block_start: Slot,
+    block_end: Slot,
+    options: &FetchOptions,
+) -> Result<()> {
+    if !options.store_transactions && !options.partition {
+        return Err(anyhow!("Nothing to write"));
+    }
+    let filter = filter::Filter::new(&options.filter)?;
+    let filter_json = options.filter.to_json()?;
+    info!("fetching with filter {}", filter_json);
+
+    let block_start = std::cmp::max(block_start, filter.min_slot.unwrap_or(0));
+    let block_end = std::cmp::min(
+        block_end, filter.max_slot.map_or(Slot::MAX, |max_slot| max_slot.saturating_add(1)));
+
+    // only ranges fetched with the same filter have what this fetch would write. failed blocks
+    // are tried again
+    let stage = options.stage();
+    let completed = {
+        let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+        checkpoint::load(&mut psql_client, block_start, block_end, &filter_json, stage)?
+            .completed()
+    };
+    for (start, end) in completed.iter() {
+        info!("skipping checkpointed slots {}-{}", start, end);
+    }
+
+    let queued_chunks = std::cmp::max(options.in_flight / std::cmp::max(options.chunk_size, 1), 1);
+    let (blocks_sender, blocks_receiver) =
+        crossbeam_channel::bounded::<Chunk<(Slot, ConfirmedBlock)>>(queued_chunks);
+    let (rows_sender, rows_receiver) =
+        crossbeam_channel::bounded::<Chunk<TransactionRow>>(queued_chunks);
+
+    let decoders = (0..std::cmp::max(options.decode_threads, 1))
+        .map(|_| {
+            let blocks_receiver = blocks_receiver.clone();
+            let rows_sender = rows_sender.clone();
+            let filter = filter.clone();
+            let options = options.clone();
+            std::thread::spawn(move || -> Result<()> {
+                for chunk in blocks_receiver {
+                    let mut rows = vec![];
+                    for (slot, block) in chunk.items {
+                        rows.extend(decode_block(&filter, &options, slot, block)?);
+                    }
+                    let chunk = Chunk {
+                        first_slot: chunk.first_slot,
+                        last_slot: chunk.last_slot,
+                        skipped: chunk.skipped,
+                        failed: chunk.failed,
+                        items: rows,
+                    };
+                    if rows_sender.send(chunk).is_err() {
+                        // the writer failed. it reports the error
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(blocks_receiver);
+    drop(rows_sender);
+
+    let writer_handle = {
+        let psql_config = psql_config.to_string();
+        let options = options.clone();
+        std::thread::spawn(move || -> Result<(u64, u64)> {
+            let mut writer = TransactionWriter::new(
+                psql_config.as_str(), &options, filter_json)?;
+            for chunk in rows_receiver {
+                writer.write(chunk)?;
+            }
+            writer.commit()?;
+            Ok((writer.written, writer.partitioned))
+        })
+    };
+
+    let fetch_result = rt.block_on(fetch_blocks(
+        source, block_start, block_end, &completed, options, blocks_sender));
+    let decode_result = decoders.into_iter().map(join).collect::<Result<Vec<_>>>();
+    let write_result = join(writer_handle);
+
+    // a later stage failing shows up as a hangup in the earlier ones so report from the end
+    let (written, partitioned) = write_result?;
+    decode_result?;
+    fetch_result?;
+
+    info!("finished block fetch. wrote {} new transactions, partitioned {}",
+          written, partitioned);
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use {
+    anyhow::{Result, anyhow},
+    serde::{Deserialize, Serialize},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    solana_transaction_status::TransactionWithStatusMeta,
+    std::collections::HashSet,
+};
+
+// which transactions fetch keeps. read from a TOML file, e.g
+//
+//   programs = ["TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"]
+//   accounts = ["<collection mint>", "<creator>"]
+//   include_failed = false
+//   min_slot = 150000000
+//
+// the spec is stored with every checkpointed range so it's always clear what a range contains
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterSpec {
+    // keep transactions that reference any of these programs
+    pub programs: Vec<String>,
+
+    // and, if not empty, any of these accounts
+    pub accounts: Vec<String>,
+
+    pub include_failed: bool,
+
+    pub min_slot: Option<Slot>,
+
+    pub max_slot: Option<Slot>,
+}
+
+impl Default for FilterSpec {
+    fn default() -> Self {
+        Self {
+            programs: vec![spl_token::id().to_string(), mpl_token_metadata::id().to_string()],
+            accounts: vec![],
+            include_failed: false,
+            min_slot: None,
+            max_slot: None,
+        }
+    }
+}
+
+impl FilterSpec {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| anyhow!("{}: {}", path, e))
+    }
+
+    // sorted and deduped so equal filters compare equal in the DB
+    pub fn canonical(mut self) -> Self {
+        self.programs.sort();
+        self.programs.dedup();
+        self.accounts.sort();
+        self.accounts.dedup();
+        self
+    }
+
+    // what checkpoints are keyed by. min_slot / max_slot only narrow the range fetch covers and
+    // that range is what gets recorded, so they're left out and ranges fetched with different
+    // bounds count towards each other
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        let spec = self.clone().canonical();
+        Ok(serde_json::json!({
+            "programs": spec.programs,
+            "accounts": spec.accounts,
+            "include_failed": spec.include_failed,
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    programs: HashSet<Pubkey>,
+
+    accounts: HashSet<Pubkey>,
+
+    include_failed: bool,
+
+    pub min_slot: Option<Slot>,
+
+    pub max_slot: Option<Slot>,
+}
+
+impl Filter {
+    pub fn new(spec: &FilterSpec) -> Result<Self> {
+        let parse = |keys: &[String]| keys
+            .iter()
+            .map(|k| k.parse::<Pubkey>().map_err(|_| anyhow!("Invalid pubkey {} in filter", k)))
+            .collect::<Result<HashSet<_>>>();
+        if spec.programs.is_empty() {
+            return Err(anyhow!("Filter needs at least one program"));
+        }
+        Ok(Self {
+            programs: parse(&spec.programs)?,
+            accounts: parse(&spec.accounts)?,
+            include_failed: spec.include_failed,
+            min_slot: spec.min_slot,
+            max_slot: spec.max_slot,
+        })
+    }
+
+    pub fn keep(&self, slot: Slot, transaction: &TransactionWithStatusMeta) -> bool {
+        if self.min_slot.map_or(false, |min_slot| slot < min_slot)
+            || self.max_slot.map_or(false, |max_slot| slot > max_slot)
+        {
+            return false;
+        }
+
+        if !self.include_failed
+            && transaction.get_status_meta().map(|m| m.status.is_err()) == Some(true)
+        {
+            return false;
+        }
+
+        let account_keys = transaction.account_keys();
+        account_keys.iter().any(|k| self.programs.contains(k))
+            && (self.accounts.is_empty() || account_keys.iter().any(|k| self.accounts.contains(k)))
+    }
+}
@@ -0,0 +1,609 @@
+use {
+    anyhow::{Result, anyhow},
+    async_trait::async_trait,
+    log::*,
+    prost::Message,
+    serde_json::{json, Value},
+    solana_ledger::blockstore::{Blockstore, BlockstoreOptions},
+    solana_ledger::blockstore_db::AccessType,
+    solana_sdk::clock::Slot,
+    solana_storage_proto::convert::generated,
+    solana_transaction_status::{ConfirmedBlock, UiConfirmedBlock},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+};
+
+// where fetch reads confirmed blocks from. every source goes through the same chunking, filtering
+// and insert path so they only need to list slots and hand back blocks
+#[async_trait]
+pub trait LedgerSource: Send + Sync {
+    // up to `limit` confirmed slots starting at (and including) `start_slot`, ascending
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>>;
+
+    async fn get_blocks(&self, slots: &[Slot]) -> Result<Vec<(Slot, ConfirmedBlock)>>;
+}
+
+#[derive(Debug, Clone)]
+pub struct BigTableConfig {
+    // falls back to GOOGLE_APPLICATION_CREDENTIALS when None
+    pub credential_path: Option<String>,
+
+    // host:port of a bigtable emulator. credentials aren't needed (or used) when set. see
+    // `set_emulator_env`
+    pub emulator_host: Option<String>,
+
+    pub instance_name: String,
+
+    // routes the requests, e.g to a replica cluster kept for reads
+    pub app_profile_id: String,
+
+    pub timeout: Option<Duration>,
+}
+
+impl Default for BigTableConfig {
+    fn default() -> Self {
+        Self {
+            credential_path: None,
+            emulator_host: None,
+            instance_name: solana_storage_bigtable::DEFAULT_INSTANCE_NAME.to_string(),
+            app_profile_id: DEFAULT_APP_PROFILE_ID.to_string(),
+            timeout: None,
+        }
+    }
+}
+
+// the app profile bigtable uses when none is given
+pub const DEFAULT_APP_PROFILE_ID: &str = "default";
+
+const EMULATOR_HOST_VAR: &str = "BIGTABLE_EMULATOR_HOST";
+
+// solana_storage_bigtable only picks the emulator up from the environment. setting it is process
+// wide, so this is for `main` to call once before starting any threads
+pub fn set_emulator_env(emulator_host: &str) {
+    std::env::set_var(EMULATOR_HOST_VAR, emulator_host);
+}
+
+impl BigTableConfig {
+    pub async fn connect(&self, read_only: bool) -> Result<solana_storage_bigtable::LedgerStorage> {
+        let env_host = std::env::var(EMULATOR_HOST_VAR).ok();
+        if let Some(emulator_host) = &self.emulator_host {
+            if env_host.as_ref() != Some(emulator_host) {
+                return Err(anyhow!(
+                    "{} must be set to the emulator host {} (see set_emulator_env)",
+                    EMULATOR_HOST_VAR, emulator_host));
+            }
+        }
+        Ok(solana_storage_bigtable::LedgerStorage::new_with_config(
+            solana_storage_bigtable::LedgerStorageConfig {
+                read_only,
+                timeout: self.timeout,
+                credential_type: solana_storage_bigtable::CredentialType::Filepath(
+                    self.credential_path.clone()),
+                instance_name: self.instance_name.clone(),
+                app_profile_id: self.app_profile_id.clone(),
+            },
+        ).await?)
+    }
+}
+
+pub struct BigTableSource {
+    bt: solana_storage_bigtable::LedgerStorage,
+}
+
+impl BigTableSource {
+    pub async fn new(config: &BigTableConfig) -> Result<Self> {
+        Ok(Self {
+            bt: config.connect(true).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl LedgerSource for BigTableSource {
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        Ok(self.bt.get_confirmed_blocks(start_slot, limit).await?)
+    }
+
+    async fn get_blocks(&self, slots: &[Slot]) -> Result<Vec<(Slot, ConfirmedBlock)>> {
+        Ok(self.bt.get_confirmed_blocks_with_data(slots).await?.collect())
+    }
+}
+
+// a validator ledger directory (or an archive of one). opened as a secondary so a running
+// validator can keep writing to it
+pub struct BlockstoreSource {
+    blockstore: Blockstore,
+}
+
+impl BlockstoreSource {
+    pub fn new(ledger_path: &Path) -> Result<Self> {
+        Ok(Self {
+            blockstore: Blockstore::open_with_options(
+                ledger_path,
+                BlockstoreOptions {
+                    access_type: AccessType::Secondary,
+                    enforce_ulimit_nofile: false,
+                    ..BlockstoreOptions::default()
+                },
+            )?,
+        })
+    }
+}
+
+#[async_trait]
+impl LedgerSource for BlockstoreSource {
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        Ok(self.blockstore
+            .slot_meta_iterator(start_slot)?
+            .map(|(slot, _)| slot)
+            .filter(|slot| self.blockstore.is_root(*slot))
+            .take(limit)
+            .collect())
+    }
+
+    async fn get_blocks(&self, slots: &[Slot]) -> Result<Vec<(Slot, ConfirmedBlock)>> {
+        slots
+            .iter()
+            .map(|slot| {
+                // archives don't always have the parent so don't require the previous blockhash
+                let block = self.blockstore.get_rooted_block(*slot, false)?;
+                Ok((*slot, ConfirmedBlock::from(block)))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DumpFormat {
+    // `getBlock` responses (the `result` or the whole response) with json, base58 or base64
+    // transaction encoding and full transaction details
+    Json,
+
+    // solana_storage_proto ConfirmedBlock, the same encoding as bigtable minus the compression
+    Protobuf,
+}
+
+// a directory with one file per block named by its slot, e.g 150000000.json. anything that
+// doesn't parse as a slot is ignored
+pub struct DumpSource {
+    format: DumpFormat,
+
+    files: BTreeMap<Slot, PathBuf>,
+}
+
+impl DumpSource {
+    pub fn new(dir: &Path, format: DumpFormat) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let slot = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<Slot>().ok());
+            if let Some(slot) = slot {
+                files.insert(slot, path);
+            }
+        }
+        Ok(Self { format, files })
+    }
+
+    fn read_block(&self, slot: Slot) -> Result<ConfirmedBlock> {
+        let path = self.files.get(&slot).ok_or(anyhow!("No dump for slot {}", slot))?;
+        let content = std::fs::read(path)?;
+        match self.format {
+            DumpFormat::Json => {
+                let mut value = serde_json::from_slice::<serde_json::Value>(&content)?;
+                if let Some(result) = value.get_mut("result") {
+                    value = result.take();
+                }
+                let block = serde_json::from_value::<UiConfirmedBlock>(value)?;
+                bonbon::convert::convert_block(block)
+                    .map_err(|e| anyhow!("{}: {:?}", path.display(), e))
+            }
+            DumpFormat::Protobuf => {
+                let block = generated::ConfirmedBlock::decode(content.as_slice())?;
+                ConfirmedBlock::try_from(block)
+                    .map_err(|e| anyhow!("{}: {}", path.display(), e))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LedgerSource for DumpSource {
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        Ok(self.files.range(start_slot..).map(|(slot, _)| *slot).take(limit).collect())
+    }
+
+    async fn get_blocks(&self, slots: &[Slot]) -> Result<Vec<(Slot, ConfirmedBlock)>> {
+        slots
+            .iter()
+            .map(|slot| Ok((*slot, self.read_block(*slot)?)))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+
+    pub message: String,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "rpc error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+// https://github.com/solana-labs/solana/blob/v1.11.5/rpc/src/custom_error.rs
+const BLOCK_NOT_AVAILABLE: i64 = -32004;
+const NODE_UNHEALTHY: i64 = -32005;
+const SLOT_SKIPPED: i64 = -32007;
+const LONG_TERM_STORAGE_SLOT_SKIPPED: i64 = -32009;
+
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub url: String,
+
+    // spread requests out so we stay under the node's rate limit
+    pub requests_per_second: f64,
+
+    // for 429s, 5xxs, timeouts and blocks the node doesn't have yet
+    pub max_retries: u32,
+
+    pub timeout: Duration,
+
+    pub max_supported_transaction_version: u8,
+}
+
+// JSON-RPC getBlocksWithLimit / getBlock. blocks are requested base64 encoded and go through
+// bonbon::convert the same as the json dumps
+pub struct RpcSource {
+    client: reqwest::Client,
+
+    config: RpcConfig,
+
+    next_request: Mutex<Instant>,
+}
+
+impl RpcSource {
+    pub fn new(config: RpcConfig) -> Result<Self> {
+        if config.requests_per_second <= 0.0 {
+            return Err(anyhow!("requests_per_second must be positive"));
+        }
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()?,
+            config,
+            next_request: Mutex::new(Instant::now()),
+        })
+    }
+
+    async fn throttle(&self) {
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let at = std::cmp::max(*next_request, now);
+            *next_request = at + Duration::from_secs_f64(1.0 / self.config.requests_per_second);
+            at - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // one attempt. errors that are worth retrying come with Some(retry_after), where retry_after
+    // is how long the node asked us to wait if it said
+    async fn try_call(
+        &self,
+        method: &str,
+        params: &Value,
+    ) -> std::result::Result<Value, (anyhow::Error, Option<Option<Duration>>)> {
+        self.throttle().await;
+
+        let response = self.client
+            .post(self.config.url.as_str())
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(|e| (e.into(), Some(None)))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err((anyhow!("{} returned {}", method, status), Some(retry_after)));
+        }
+
+        let mut body = response
+            .error_for_status()
+            .map_err(|e| (e.into(), None))?
+            .json::<Value>()
+            .await
+            .map_err(|e| (e.into(), Some(None)))?;
+
+        if let Some(error) = body.get("error") {
+            let error = RpcError {
+                code: error.get("code").and_then(Value::as_i64).unwrap_or_default(),
+                message: error.get("message").and_then(Value::as_str).unwrap_or_default()
+                    .to_string(),
+            };
+            let retry = matches!(error.code, BLOCK_NOT_AVAILABLE | NODE_UNHEALTHY)
+                .then(|| None);
+            return Err((error.into(), retry));
+        }
+
+        Ok(body.get_mut("result").map(Value::take).unwrap_or(Value::Null))
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 0;
+        loop {
+            match self.try_call(method, &params).await {
+                Ok(result) => return Ok(result),
+                Err((err, Some(retry_after))) if attempt < self.config.max_retries => {
+                    let delay = retry_after.unwrap_or(backoff);
+                    warn!("{} failed ({}), retrying in {:?}", method, err, delay);
+                    tokio::time::sleep(delay).await;
+                    backoff = std::cmp::min(backoff * 2, Duration::from_secs(30));
+                    attempt += 1;
+                }
+                Err((err, _)) => return Err(err),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LedgerSource for RpcSource {
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        let result = self.call(
+            "getBlocksWithLimit",
+            json!([start_slot, limit, { "commitment": "finalized" }]),
+        ).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    async fn get_blocks(&self, slots: &[Slot]) -> Result<Vec<(Slot, ConfirmedBlock)>> {
+        let mut blocks = Vec::with_capacity(slots.len());
+        for slot in slots {
+            let result = self.call(
+                "getBlock",
+                json!([slot, {
+                    "commitment": "finalized",
+                    "encoding": "base64",
+                    "transactionDetails": "full",
+                    "rewards": false,
+                    "maxSupportedTransactionVersion":
+                        self.config.max_supported_transaction_version,
+                }]),
+            ).await;
+
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => match err.downcast_ref::<RpcError>() {
+                    Some(RpcError { code: SLOT_SKIPPED | LONG_TERM_STORAGE_SLOT_SKIPPED, .. }) => {
+                        warn!("skipping slot {}: {}", slot, err);
+                        continue;
+                    }
+                    _ => return Err(err),
+                },
+            };
+
+            let block = serde_json::from_value::<UiConfirmedBlock>(result)?;
+            blocks.push((
+                *slot,
+                bonbon::convert::convert_block(block)
+                    .map_err(|e| anyhow!("slot {}: {:?}", slot, e))?,
+            ));
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            hash::Hash,
+            pubkey::Pubkey,
+            signature::{Keypair, Signer},
+            system_instruction,
+            transaction::Transaction,
+        },
+        solana_transaction_status::{
+            BlockEncodingOptions, TransactionDetails, TransactionWithStatusMeta,
+            UiTransactionEncoding,
+        },
+        std::{
+            io::{BufRead, BufReader, Read, Write},
+            net::TcpListener,
+            sync::{atomic::{AtomicUsize, Ordering}, Arc},
+        },
+    };
+
+    // plain HTTP/1.1 JSON-RPC server on a free port. `respond` gets each request body and returns
+    // the status and body to answer with
+    fn serve<F>(respond: F) -> String
+    where
+        F: Fn(Value) -> (u16, Value) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let respond = respond.clone();
+                std::thread::spawn(move || -> std::io::Result<()> {
+                    let mut stream = stream?;
+                    let mut reader = BufReader::new(stream.try_clone()?);
+                    loop {
+                        let mut content_length = 0;
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line)? == 0 {
+                                return Ok(());
+                            }
+                            if line.trim_end().is_empty() {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body)?;
+
+                        let (status, body) = respond(serde_json::from_slice(&body).unwrap());
+                        let body = body.to_string();
+                        write!(
+                            stream,
+                            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n\
+                             Retry-After: 0\r\nContent-Length: {}\r\n\r\n{}",
+                            status, body.len(), body,
+                        )?;
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn source(url: String) -> RpcSource {
+        RpcSource::new(RpcConfig {
+            url,
+            requests_per_second: 1000.0,
+            max_retries: 3,
+            timeout: Duration::from_secs(10),
+            max_supported_transaction_version: 0,
+        }).unwrap()
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn result(result: Value) -> (u16, Value) {
+        (200, json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+    }
+
+    fn error(code: i64) -> (u16, Value) {
+        (200, json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": code, "message": "mock" } }))
+    }
+
+    fn transfer() -> Transaction {
+        let payer = Keypair::new();
+        Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        )
+    }
+
+    // getBlock's base64 response for a block with `transactions`
+    fn encoded_block(transactions: Vec<TransactionWithStatusMeta>) -> Value {
+        let block = ConfirmedBlock {
+            previous_blockhash: Hash::default().to_string(),
+            blockhash: Hash::new_unique().to_string(),
+            parent_slot: 0,
+            transactions,
+            rewards: vec![],
+            block_time: None,
+            block_height: None,
+        };
+        let encoded = block.encode_with_options(
+            UiTransactionEncoding::Base64,
+            BlockEncodingOptions {
+                transaction_details: TransactionDetails::Full,
+                show_rewards: false,
+                max_supported_transaction_version: Some(0),
+            },
+        ).unwrap();
+        serde_json::to_value(encoded).unwrap()
+    }
+
+    #[test]
+    fn retries_rate_limited_requests() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = serve(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => (429, Value::Null),
+            _ => result(json!([5, 6, 8])),
+        });
+
+        let slots = block_on(source(url).get_confirmed_blocks(5, 3)).unwrap();
+        assert_eq!(slots, vec![5, 6, 8]);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            (429, Value::Null)
+        });
+
+        assert!(block_on(source(url).get_confirmed_blocks(5, 3)).is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn converts_blocks_and_skips_missing_slots() {
+        let transaction = transfer();
+        let block = encoded_block(vec![
+            TransactionWithStatusMeta::MissingMetadata(transaction.clone()),
+        ]);
+        let url = serve(move |request| match request["params"][0].as_u64() {
+            Some(5) => result(block.clone()),
+            Some(6) => error(SLOT_SKIPPED),
+            Some(7) => error(LONG_TERM_STORAGE_SLOT_SKIPPED),
+            _ => error(-32000),
+        });
+
+        let blocks = block_on(source(url).get_blocks(&[5, 6, 7])).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].0, 5);
+        assert_eq!(
+            blocks[0].1.transactions,
+            vec![TransactionWithStatusMeta::MissingMetadata(transaction)],
+        );
+    }
+
+    #[test]
+    fn rejects_blocks_without_transactions() {
+        let mut block = encoded_block(vec![]);
+        block.as_object_mut().unwrap().remove("transactions");
+        let url = serve(move |_| result(block.clone()));
+
+        let err = block_on(source(url).get_blocks(&[5])).unwrap_err();
+        assert!(err.to_string().contains("MissingTransactions"), "{}", err);
+    }
+}
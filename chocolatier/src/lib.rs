@@ -0,0 +1,14 @@
+pub mod activity;
+pub mod checkpoint;
+pub mod collection;
+pub mod convert;
+pub mod coverage;
+pub mod export;
+pub mod fetch;
+pub mod filter;
+pub mod ledger;
+pub mod owner;
+pub mod partition;
+pub mod resolve;
+pub mod storage;
+pub mod writer;
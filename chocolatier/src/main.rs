@@ -1,34 +1,31 @@
 use {
     log::*,
     anyhow::{Result, anyhow},
+    chocolatier::{
+        activity, collection, convert, coverage, export, fetch, filter, ledger, owner, partition,
+        resolve, storage, writer,
+    },
     postgres::fallible_iterator::FallibleIterator,
-    prost::Message,
     solana_sdk::{
         clock::Slot,
         instruction::CompiledInstruction,
         pubkey::Pubkey,
+        signature::Signature,
     },
-    solana_storage_proto::convert::generated,
-    solana_transaction_status::TransactionWithStatusMeta,
 };
 
-pub mod convert;
-
 #[derive(Debug)]
 pub struct Config {
     psql_config: String,
     log_file: String,
+    batch_size: usize,
 }
 
-async fn fetch(
-    config: &Config,
-    bigtable_path: String,
-    block_range: String,
-) -> Result<()> {
+fn parse_block_range(block_range: &str) -> Result<(Slot, Slot)> {
     let re = regex::Regex::new(r"^(\d*)-(\d*)$")?;
 
-    let (block_start, block_end) = (|| -> Option<(Slot, Slot)> {
-        let caps = re.captures(block_range.as_str())?;
+    (|| -> Option<(Slot, Slot)> {
+        let caps = re.captures(block_range)?;
         let block_start = caps.get(1)?.as_str().parse::<Slot>().ok()?;
         let block_end = caps.get(2)?.as_str().parse::<Slot>().ok()?;
         if block_start > block_end {
@@ -36,225 +33,391 @@ async fn fetch(
         } else {
             Some((block_start, block_end))
         }
-    })().ok_or(anyhow!("Invalid --block_range"))?;
+    })().ok_or(anyhow!("Invalid --block_range"))
+}
 
-    let (psql_client, psql_connection) = tokio_postgres::connect(
-        config.psql_config.as_str(), tokio_postgres::NoTls).await?;
+fn fetch(
+    config: &Config,
+    rt: &tokio::runtime::Runtime,
+    source: &dyn ledger::LedgerSource,
+    block_range: String,
+    options: fetch::FetchOptions,
+) -> Result<()> {
+    let (block_start, block_end) = parse_block_range(block_range.as_str())?;
 
-    let psql_join_handle = tokio::spawn(async move {
-        if let Err(e) = psql_connection.await {
-            eprintln!("connection error: {}", e);
-        }
-    });
+    fetch::fetch(
+        config.psql_config.as_str(), rt, source, block_start, block_end, &options)
+}
 
-    let insert_transaction_statement = psql_client.prepare(
-        "INSERT INTO transactions VALUES ($1, $2, $3, $4)"
-    ).await?;
+fn print_coverage(report: &coverage::Report) {
+    println!("slots {}-{}: {} blocks, {} skipped, {} failed, {} unfetched ranges, {} transactions",
+             report.start, report.end, report.blocks, report.skipped, report.failed.len(),
+             report.unfetched.len(), report.transactions);
+    for (start, end) in &report.unfetched {
+        println!("  unfetched {}-{}", start, end);
+    }
+    for slot in &report.failed {
+        println!("  failed {}", slot);
+    }
+}
 
-    let bt = solana_storage_bigtable::LedgerStorage::new(
-        true, None, Some(bigtable_path)).await.unwrap();
+fn coverage(
+    config: &Config,
+    block_range: String,
+    options: fetch::FetchOptions,
+    refill: Option<(&tokio::runtime::Runtime, &dyn ledger::LedgerSource)>,
+) -> Result<()> {
+    let (block_start, block_end) = parse_block_range(block_range.as_str())?;
+    let filter_json = options.filter.to_json()?;
 
-    // TODO: parameterize?
-    let chunk_size = 16;
-    let mut chunk_start = block_start;
-    while chunk_start < block_end {
-        let limit = std::cmp::min(chunk_size, block_end - chunk_start);
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+    let report = coverage::report(
+        &mut psql_client, block_start, block_end, &filter_json, options.stage())?;
+    print_coverage(&report);
+
+    if let Some((rt, source)) = refill {
+        let holes = report.holes();
+        if holes.is_empty() {
+            return Ok(());
+        }
+        // fetch skips everything that's already covered so only the holes are requested
+        for (start, end) in holes.iter() {
+            info!("refilling slots {}-{}", start, end);
+            fetch::fetch(config.psql_config.as_str(), rt, source, start, end + 1, &options)?;
+        }
+        print_coverage(&coverage::report(
+            &mut psql_client, block_start, block_end, &filter_json, options.stage())?);
+    }
 
-        let chunk_slots = bt.get_confirmed_blocks(
-            chunk_start, limit as usize).await?;
+    Ok(())
+}
 
-        for (slot, block) in bt.get_confirmed_blocks_with_data(&chunk_slots).await? {
-            let slot = slot as i64;
-            for (index, transaction) in block.transactions.into_iter().enumerate() {
-                // skip errors
-                if transaction.get_status_meta().map(|m| m.status.is_err()) == Some(true) {
-                    continue;
-                }
-                let index = index as i64;
-                let mut found_token_or_metadata = false;
-                for account_key in transaction.account_keys().iter() {
-                    if *account_key == spl_token::id() || *account_key == mpl_token_metadata::id() {
-                        found_token_or_metadata = true;
-                        break;
-                    }
-                }
-                if !found_token_or_metadata { continue; }
-
-                // TODO: dedup some work in bigtable library?
-                let signature = transaction.transaction_signature().clone();
-                let protobuf_tx = generated::ConfirmedTransaction::from(transaction);
-                let mut buf = Vec::with_capacity(protobuf_tx.encoded_len());
-                protobuf_tx.encode(&mut buf).unwrap();
-                // TODO: compress?
-
-                psql_client.query(
-                    &insert_transaction_statement,
-                    &[
-                        &slot,
-                        &index,
-                        &signature.as_ref(),
-                        &buf,
-                    ],
-                ).await?;
+fn fetch_options(config: &Config, sub_m: &clap::ArgMatches) -> Result<fetch::FetchOptions> {
+    let parse_count = |name: &str| sub_m.value_of(name).unwrap()
+        .parse::<usize>().map_err(|_| anyhow!("Invalid --{}", name));
+    // an empty chunk would list no slots and end the fetch straight away
+    let chunk_size = parse_count("chunk_size")?;
+    if chunk_size == 0 {
+        return Err(anyhow!("--chunk_size must be positive"));
+    }
+    Ok(fetch::FetchOptions {
+        chunk_size,
+        concurrency: parse_count("concurrency")?,
+        decode_threads: parse_count("decode_threads")?,
+        in_flight: parse_count("in_flight")?,
+        batch_size: config.batch_size,
+        filter: filter_spec(sub_m)?,
+        store_transactions: true,
+        partition: false,
+    })
+}
+
+fn ledger_source(
+    rt: &tokio::runtime::Runtime,
+    sub_m: &clap::ArgMatches,
+) -> Result<Box<dyn ledger::LedgerSource>> {
+    let ledger_path = || sub_m.value_of("ledger_path")
+        .map(std::path::Path::new)
+        .ok_or(anyhow!("Missing --ledger_path"));
+    let source_kind = match sub_m.value_of("rpc") {
+        Some(_) => "rpc",
+        None => sub_m.value_of("source").unwrap(),
+    };
+    Ok(match source_kind {
+        "bigtable" => {
+            let emulator_host = sub_m.value_of("bigtable_emulator_host");
+            let credential_path = sub_m.value_of("bigtable_path");
+            if emulator_host.is_none() && credential_path.is_none() {
+                return Err(anyhow!("Missing --bigtable_path or --bigtable_emulator_host"));
+            }
+            let mut bigtable_config = ledger::BigTableConfig {
+                credential_path: credential_path.map(|p| p.to_string()),
+                emulator_host: emulator_host.map(|h| h.to_string()),
+                ..ledger::BigTableConfig::default()
+            };
+            if let Some(instance_name) = sub_m.value_of("bigtable_instance") {
+                bigtable_config.instance_name = instance_name.to_string();
+            }
+            if let Some(app_profile_id) = sub_m.value_of("bigtable_app_profile") {
+                bigtable_config.app_profile_id = app_profile_id.to_string();
             }
+            if let Some(timeout) = sub_m.value_of("bigtable_timeout_secs") {
+                bigtable_config.timeout = Some(std::time::Duration::from_secs(
+                    timeout.parse::<u64>()
+                        .map_err(|_| anyhow!("Invalid --bigtable_timeout_secs"))?));
+            }
+            Box::new(rt.block_on(ledger::BigTableSource::new(&bigtable_config))?)
         }
+        "blockstore" => Box::new(ledger::BlockstoreSource::new(ledger_path()?)?),
+        "json" => Box::new(ledger::DumpSource::new(ledger_path()?, ledger::DumpFormat::Json)?),
+        "protobuf" => Box::new(
+            ledger::DumpSource::new(ledger_path()?, ledger::DumpFormat::Protobuf)?),
+        "rpc" => Box::new(ledger::RpcSource::new(ledger::RpcConfig {
+            url: sub_m.value_of("rpc")
+                .ok_or(anyhow!("Missing --rpc"))?.to_string(),
+            requests_per_second: sub_m.value_of("rpc_requests_per_sec")
+                .unwrap().parse::<f64>()
+                .map_err(|_| anyhow!("Invalid --rpc_requests_per_sec"))?,
+            max_retries: sub_m.value_of("rpc_max_retries")
+                .unwrap().parse::<u32>()
+                .map_err(|_| anyhow!("Invalid --rpc_max_retries"))?,
+            timeout: std::time::Duration::from_secs(
+                sub_m.value_of("rpc_timeout_secs")
+                    .unwrap().parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid --rpc_timeout_secs"))?),
+            max_supported_transaction_version:
+                sub_m.value_of("max_supported_transaction_version")
+                    .unwrap().parse::<u8>()
+                    .map_err(|_| anyhow!("Invalid --max_supported_transaction_version"))?,
+        })?),
+        other => return Err(anyhow!("Unknown --source {}", other)),
+    })
+}
 
-        chunk_start = chunk_slots.last().unwrap_or(&block_end) + 1;
+fn filter_spec(sub_m: &clap::ArgMatches) -> Result<filter::FilterSpec> {
+    let mut spec = match sub_m.value_of("filter_config") {
+        Some(path) => filter::FilterSpec::from_file(path)?,
+        None => filter::FilterSpec::default(),
+    };
+    if let Some(programs) = sub_m.values_of("program") {
+        spec.programs = programs.map(|p| p.to_string()).collect();
     }
+    if let Some(accounts) = sub_m.values_of("account") {
+        spec.accounts = accounts.map(|a| a.to_string()).collect();
+    }
+    if sub_m.is_present("include_failed") {
+        spec.include_failed = true;
+    }
+    if let Some(min_slot) = sub_m.value_of("min_slot") {
+        spec.min_slot = Some(min_slot.parse::<Slot>().map_err(|_| anyhow!("Invalid --min_slot"))?);
+    }
+    if let Some(max_slot) = sub_m.value_of("max_slot") {
+        spec.max_slot = Some(max_slot.parse::<Slot>().map_err(|_| anyhow!("Invalid --max_slot"))?);
+    }
+    Ok(spec)
+}
 
-    info!("finished block fetch. waiting for db join...");
-
-    drop(psql_client);
-    psql_join_handle.await?;
+// without a range, partitions what's new since the last run. with one, replaces the derived rows
+// for the inclusive slot range
+fn partition(
+    config: &Config,
+    from: Option<Slot>,
+    to: Option<Slot>,
+    workers: usize,
+) -> Result<()> {
+    let partition_start = std::time::Instant::now();
+    let stats = if from.is_none() && to.is_none() {
+        partition::partition_new(config.psql_config.as_str(), workers, config.batch_size)?
+    } else {
+        let mut psql_client = postgres::Client::connect(
+            config.psql_config.as_str(), postgres::NoTls)?;
+        let (first, last) = match partition::bounds(&mut psql_client)? {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        let start = from.map_or(first, |from| (from as i64, 0));
+        let end = to.map_or((last.0, last.1 + 1), |to| (to as i64 + 1, 0));
+        info!("re-partitioning {:?} up to {:?}", start, end);
+        partition::partition(config.psql_config.as_str(), start, end, workers, config.batch_size)?
+    };
+    log::info!("partitioned {} transactions in {:?}",
+               stats.transactions, partition_start.elapsed());
+    print_partition_stats(&stats);
 
     Ok(())
 }
 
-fn partition(config: &Config) -> Result<()> {
-    use bonbon::partition::*;
-    let partitioners = [
-        InstructionPartitioner {
-            partitioner: partition_token_instruction,
-            program_id: spl_token::id(),
-        },
-        InstructionPartitioner {
-            partitioner: partition_metadata_instruction,
-            program_id: mpl_token_metadata::id(),
-        },
-    ];
-
-    let mut psql_client = postgres::Client::connect(
-        config.psql_config.as_str(), postgres::NoTls)?;
+fn print_partition_stats(stats: &partition::Stats) {
+    println!("{} transactions, {} failed to partition",
+             stats.transactions, stats.failed_transactions);
+    if stats.instructions.is_empty() {
+        return;
+    }
+    println!("{:<44} {:<32} {:>12} {:>12} {:>12} {:>12}",
+             "program", "variant", "partitioned", "ignored", "none", "failed");
+    for ((program_key, variant), counts) in &stats.instructions {
+        println!("{:<44} {:<32} {:>12} {:>12} {:>12} {:>12}",
+                 program_key.to_string(),
+                 variant.unwrap_or("-"),
+                 counts.partitioned,
+                 counts.ignored,
+                 counts.returned_none,
+                 counts.failed);
+    }
+}
 
-    let select_all_statement = psql_client.prepare(
-        "SELECT *
-         FROM transactions
-         ORDER BY (slot, block_index)
-        ",
-    )?;
+const SELECT_PARTITION_KEY: &str =
+    "SELECT p.signature, p.instruction, a.keys, a.metas,
+            p.slot, p.block_index, p.outer_index, p.inner_index
+     FROM partitions p JOIN account_keys a ON p.signature = a.signature
+     WHERE partition_key = decode($1, 'base64')
+        OR partition_key = decode($2, 'base64')
+     ORDER BY (slot, block_index, outer_index, inner_index)
+    ";
+
+#[derive(Default)]
+struct AssembleTimings {
+    partition_queries: std::time::Duration,
+    deserialization: std::time::Duration,
+}
 
-    let mut insert_client = postgres::Client::connect(
-        config.psql_config.as_str(), postgres::NoTls)?;
+// replays the partitioned instructions for `mint_key` (and its metadata) into a bonbon. if `at`
+// is set, instructions after it are dropped so the bonbon is as it was at that point. returns
+// None (after logging) for mints that fail to assemble or never got metadata
+fn assemble_bonbon(
+    psql_client: &mut postgres::Client,
+    select_partition_key: &postgres::Statement,
+    updaters: &[bonbon::assemble::BonbonUpdater<CompiledInstruction>],
+    mint_key: Pubkey,
+    at: Option<&bonbon::assemble::PointInTime>,
+    timings: &mut AssembleTimings,
+) -> Result<Option<bonbon::assemble::Bonbon>> {
+    use bonbon::assemble::*;
+    let metadata_key = mpl_token_metadata::pda::find_metadata_account(&mint_key).0;
 
-    let insert_partition_statement = insert_client.prepare(
-        "INSERT INTO partitions VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    let mint_key_encoded = base64::encode(&mint_key);
+    let metadata_key_encoded = base64::encode(&metadata_key);
+    let query_start = std::time::Instant::now();
+    let instructions = psql_client.query(
+        select_partition_key,
+        &[&mint_key_encoded, &metadata_key_encoded],
     )?;
+    timings.partition_queries += query_start.elapsed();
+
+    let mut bonbon = Bonbon::default();
+    let mut update_err = None;
+    let mut transient_metas = vec![];
+    let instructions = instructions.into_iter().map(|row| {
+        let instruction_index = InstructionIndex {
+            slot: row.get(4),
+            block_index: row.get(5),
+            outer_index: row.get(6),
+            inner_index: row.get(7),
+        };
+        (row, instruction_index)
+    });
+    for (row, instruction_index) in replay_until(instructions, at) {
+        let deserialization_start = std::time::Instant::now();
+        let signature = Signature::new(&row.get::<_, Vec<u8>>(0));
+        let instruction = bincode::deserialize
+            ::<CompiledInstruction>(&row.get::<_, Vec<u8>>(1))?;
+
+        let keys: Vec<convert::SqlPubkey> = row.get(2);
+        let keys = keys.into_iter().map(|k| k.0).collect::<Vec<_>>();
+
+        let metas: Vec<convert::TransactionTokenMeta> = row.get(3);
+        let metas = metas.into_iter().map(|m| TransactionTokenOwnerMeta {
+            account_index: m.account_index as u8, // TODO: check?
+            owner_key: m.owner_key.map(|p| p.0),
+        }).collect::<Vec<_>>();
+        timings.deserialization += deserialization_start.elapsed();
+
+        let instruction_context = InstructionContext {
+            account_keys: &keys,
+            instruction: &instruction,
+            owners: &metas,
+            instruction_index: instruction_index.clone(),
+            signature,
+            transient_metas: &mut transient_metas,
+        };
 
-    let insert_other_statement = insert_client.prepare(
-        "INSERT INTO partition_failures VALUES ($1, $2, $3, $4, $5, $6, $7)"
-    )?;
+        match bonbon.update(instruction_context, updaters) {
+            Ok(_) => {}
+            Err(err) => {
+                update_err = Some((err, instruction_index));
+                break;
+            }
+        }
+    }
 
-    let insert_account_keys_statement = insert_client.prepare(
-        "INSERT INTO account_keys VALUES ($1, $2, $3)"
-    )?;
+    if let Some(err) = update_err {
+        warn!("failed to make bonbon {}: {:?}",
+              mint_key, err);
+        return Ok(None);
+    }
 
-    let params: &[&str] = &[];
-    let query_start = std::time::Instant::now();
-    let mut it = psql_client.query_raw(
-        &select_all_statement,
-        params,
-    )?;
-    log::info!("initial query took {:?}", query_start.elapsed());
+    if bonbon.metadata_key == Pubkey::default() {
+        return Ok(None);
+    }
 
-    let loop_start = std::time::Instant::now();
-    while let Some(row) = it.next()? {
-        let slot: i64 = row.get(0);
-        let block_index: i64 = row.get(1);
-        let signature: Vec<u8> = row.get(2);
-        let transaction: Vec<u8> = row.get(3);
-
-        let transaction = generated::ConfirmedTransaction::decode(&transaction[..])?;
-        let transaction = TransactionWithStatusMeta::try_from(transaction)?;
-
-        // skip errors
-        if transaction.get_status_meta().map(|m| m.status.is_err()) == Some(true) {
-            continue;
-        }
-
-        let account_keys = transaction.account_keys()
-            .iter().map(|k| k.as_ref().to_vec()).collect::<Vec<_>>();
-
-        match partition_transaction(transaction, &partitioners) {
-            Ok(bonbon::partition::Partitions { partitioned, token_metas, other }) => {
-                if partitioned.len() != 0 {
-                    insert_client.query(
-                        &insert_account_keys_statement,
-                        &[
-                            &signature.as_slice(),
-                            &account_keys,
-                            &token_metas.into_iter()
-                                .map(|m| convert::TransactionTokenMeta::from(m))
-                                .collect::<Vec<_>>(),
-                        ],
-                    )?;
-                }
-                for PartitionedInstruction {
-                    instruction,
-                    partition_key,
-                    program_key,
-                    outer_index,
-                    inner_index,
-                } in partitioned {
-                    // TODO: soft error?
-                    let serialized = bincode::serialize(&instruction)?;
-                    insert_client.query(
-                        &insert_partition_statement,
-                        &[
-                            &partition_key.as_ref(),
-                            &program_key.as_ref(),
-                            &slot,
-                            &block_index,
-                            &outer_index,
-                            &inner_index,
-                            &signature.as_slice(),
-                            &serialized,
-                        ],
-                    )?;
-                }
+    if let Some(limited_edition) = &bonbon.limited_edition {
+        // fetch the master bonbon instructions. all the glazing information can be found in
+        // the metadata instructions so we only need this `master_key`
+        let master_key_encoded = base64::encode(&limited_edition.master_key);
+        let master_instructions = psql_client.query(
+            select_partition_key,
+            &[&master_key_encoded, &master_key_encoded],
+        )?;
 
-                for OtherInstruction {
-                    reason,
-                    instruction,
-                    program_key,
-                    outer_index,
-                    inner_index,
-                } in other {
-                    match reason {
-                        Reason::PartitionFailure { error_code } => {
-                            warn!("failed to partition {}.{:04x}.{:02x}.{:?} [{}]: {:?}",
-                                  slot, block_index, outer_index, inner_index,
-                                  bs58::encode(&signature).into_string(), error_code);
-                            let serialized = bincode::serialize(&instruction)?;
-                            insert_client.query(
-                                &insert_other_statement,
-                                &[
-                                    &program_key.as_ref(),
-                                    &slot,
-                                    &block_index,
-                                    &outer_index,
-                                    &inner_index,
-                                    &signature.as_slice(),
-                                    &serialized,
-                                ],
-                            )?;
-                        }
-                        _ => {},
-                    }
-                }
-            }
-            Err(err) => {
-                warn!("failed to partition {}.{:04x} [{}]: {:?}",
-                      slot, block_index, bs58::encode(signature).into_string(), err);
-            }
+        // conversions...
+        let master_instructions = master_instructions
+            .into_iter()
+            .map(|row| {
+                let instruction_index = InstructionIndex {
+                    slot: row.get(4),
+                    block_index: row.get(5),
+                    outer_index: row.get(6),
+                    inner_index: row.get(7),
+                };
+
+                bincode::deserialize
+                    ::<CompiledInstruction>(&row.get::<_, Vec<u8>>(1))
+                    .map(|instruction| (instruction, instruction_index))
+            })
+            .collect::<Result<Vec<_>, _>>().unwrap();
+
+        // finalize the limited edition
+        let glaze_err = bonbon::assemble::glaze_limited(
+            &mut bonbon, master_instructions.as_slice());
+
+        if let Err(err) = glaze_err {
+            warn!("limited bonbon {}: failed to glaze: {:?}", mint_key, err);
+            return Ok(None);
+        }
+
+        // the inherited glazing comes first and anything after is the print's own
+        if bonbon.glazings.first().map(|g| &g.source) != Some(&GlazingSource::MasterEdition) {
+            warn!("limited bonbon {}: no master glazing", mint_key);
         }
     }
-    log::info!("partitioned in {:?}", loop_start.elapsed());
 
+    Ok(Some(bonbon))
+}
+
+fn bonbon_updaters() -> [bonbon::assemble::BonbonUpdater<CompiledInstruction>; 2] {
+    use bonbon::assemble::*;
+    [
+        BonbonUpdater {
+            update: update_token_instruction,
+            program_id: spl_token::id(),
+        },
+        BonbonUpdater {
+            update: update_metadata_instruction,
+            program_id: mpl_token_metadata::id(),
+        },
+    ]
+}
+
+// writes a batch of reassembled bonbons. their activities from an earlier run are deleted in the
+// same transaction so re-running doesn't add a second copy for `activity` to page through
+fn flush_reassembled(
+    psql_client: &mut postgres::Client,
+    writer: &mut writer::CopyWriter,
+    mint_keys: &mut Vec<String>,
+) -> Result<()> {
+    let mut db_transaction = psql_client.transaction()?;
+    db_transaction.execute(
+        "DELETE FROM activities WHERE mint_key = ANY($1)",
+        &[&*mint_keys],
+    )?;
+    mint_keys.clear();
+    writer.flush_in(&mut db_transaction)?;
+    db_transaction.commit()?;
     Ok(())
 }
 
 fn reassemble(config: &Config) -> Result<()> {
-    use bonbon::assemble::*;
     let mut psql_client = postgres::Client::connect(
         config.psql_config.as_str(), postgres::NoTls)?;
 
@@ -268,27 +431,16 @@ fn reassemble(config: &Config) -> Result<()> {
         ",
     )?;
 
-    let select_partition_key = psql_client.prepare(
-        "SELECT p.signature, p.instruction, a.keys, a.metas,
-                p.slot, p.block_index, p.outer_index, p.inner_index
-         FROM partitions p JOIN account_keys a ON p.signature = a.signature
-         WHERE partition_key = decode($1, 'base64')
-            OR partition_key = decode($2, 'base64')
-         ORDER BY (slot, block_index, outer_index, inner_index)
-        ",
-    )?;
-
-    let insert_bonbon_statement = psql_client.prepare(
-        "INSERT INTO bonbons VALUES ($1, $2, $3, $4, $5, $6, $7)"
-    )?;
-
-    let insert_glazing_statement = psql_client.prepare(
-        "INSERT INTO glazings VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"
-    )?;
+    let select_partition_key = psql_client.prepare(SELECT_PARTITION_KEY)?;
 
-    let insert_transfer_statement = psql_client.prepare(
-        "INSERT INTO transfers VALUES ($1, $2, $3, $4, $5, $6)"
-    )?;
+    let mut writer = writer::CopyWriter::new(config.batch_size);
+    let bonbons_table = writer.add_table(&mut psql_client, "bonbons")?;
+    let glazings_table = writer.add_table(&mut psql_client, "glazings")?;
+    let glazing_changes_table = writer.add_table(&mut psql_client, "glazing_changes")?;
+    let transfers_table = writer.add_table(&mut psql_client, "transfers")?;
+    let authority_changes_table = writer.add_table(&mut psql_client, "authority_changes")?;
+    let activities_table = writer.add_table(&mut psql_client, "activities")?;
+    let anomalies_table = writer.add_table(&mut psql_client, "anomalies")?;
 
     let spl_token_id_encoded = base64::encode(spl_token::id());
     let params: &[&str] = &[&spl_token_id_encoded];
@@ -299,145 +451,38 @@ fn reassemble(config: &Config) -> Result<()> {
     )?;
     log::info!("initial query took {:?}", query_start.elapsed());
 
-    let updaters = [
-        BonbonUpdater {
-            update: update_token_instruction,
-            program_id: spl_token::id(),
-        },
-        BonbonUpdater {
-            update: update_metadata_instruction,
-            program_id: mpl_token_metadata::id(),
-        },
-    ];
+    let updaters = bonbon_updaters();
+    let owner_index = owner::OwnerIndex::new(&mut psql_client)?;
+    let mut collection_index = collection::CollectionIndex::new(&mut psql_client)?;
 
     let loop_start = std::time::Instant::now();
-    let mut partition_queries = std::time::Duration::ZERO;
+    let mut timings = AssembleTimings::default();
     let mut update_queries = std::time::Duration::ZERO;
-    let mut deserialization_duration = std::time::Duration::ZERO;
+    let mut batch_mint_keys = vec![];
     while let Some(row) = it.next()? {
         let mint_key = Pubkey::new(row.get(0));
-        let metadata_key = mpl_token_metadata::pda::find_metadata_account(&mint_key).0;
-
-        let mint_key_encoded = base64::encode(&mint_key);
-        let metadata_key_encoded = base64::encode(&metadata_key);
-        let query_start = std::time::Instant::now();
-        let instructions = psql_client.query(
-            &select_partition_key,
-            &[&mint_key_encoded, &metadata_key_encoded],
-        )?;
-        partition_queries += query_start.elapsed();
-
-        let mut bonbon = Bonbon::default();
-        let mut update_err = None;
-        let mut transient_metas = vec![];
-        for row in instructions {
-            let deserialization_start = std::time::Instant::now();
-            let instruction = bincode::deserialize
-                ::<CompiledInstruction>(&row.get::<_, Vec<u8>>(1))?;
-
-            let keys: Vec<convert::SqlPubkey> = row.get(2);
-            let keys = keys.into_iter().map(|k| k.0).collect::<Vec<_>>();
-
-            let metas: Vec<convert::TransactionTokenMeta> = row.get(3);
-            let metas = metas.into_iter().map(|m| TransactionTokenOwnerMeta {
-                account_index: m.account_index as u8, // TODO: check?
-                owner_key: m.owner_key.map(|p| p.0),
-            }).collect::<Vec<_>>();
-            deserialization_duration += deserialization_start.elapsed();
-
-            let slot: i64 = row.get(4);
-            let block_index: i64 = row.get(5);
-            let outer_index: i64 = row.get(6);
-            let inner_index: Option<i64> = row.get(7);
-
-            let instruction_index = InstructionIndex {
-                slot,
-                block_index,
-                outer_index,
-                inner_index,
-            };
-            let instruction_context = InstructionContext {
-                account_keys: &keys,
-                instruction: &instruction,
-                owners: &metas,
-                instruction_index: instruction_index.clone(),
-                transient_metas: &mut transient_metas,
-            };
-
-            match bonbon.update(instruction_context, &updaters) {
-                Ok(_) => {}
-                Err(err) => {
-                    update_err = Some((err, instruction_index));
-                    break;
-                }
-            }
-        }
-
-        if let Some(err) = update_err {
-            warn!("failed to make bonbon {}: {:?}",
-                  mint_key, err);
-            continue;
-        }
-
-        if bonbon.metadata_key == Pubkey::default() {
-            continue;
-        }
-
-        if let Some(limited_edition) = &bonbon.limited_edition {
-            if !bonbon.glazings.is_empty() {
-                warn!("limited bonbon {}: had existing glazings", mint_key);
-            }
-
-            // fetch the master bonbon instructions. all the glazing information can be found in
-            // the metadata instructions so we only need this `master_key`
-            let master_key_encoded = base64::encode(&limited_edition.master_key);
-            let master_instructions = psql_client.query(
-                &select_partition_key,
-                &[&master_key_encoded, &master_key_encoded],
-            )?;
 
-            // conversions...
-            let master_instructions = master_instructions
-                .into_iter()
-                .map(|row| {
-                    let instruction_index = InstructionIndex {
-                        slot: row.get(4),
-                        block_index: row.get(5),
-                        outer_index: row.get(6),
-                        inner_index: row.get(7),
-                    };
-
-                    bincode::deserialize
-                        ::<CompiledInstruction>(&row.get::<_, Vec<u8>>(1))
-                        .map(|instruction| (instruction, instruction_index))
-                })
-                .collect::<Result<Vec<_>, _>>().unwrap();
-
-            // finalize the limited edition
-            let glaze_err = bonbon::assemble::glaze_limited(
-                &mut bonbon, master_instructions.as_slice());
-
-            if let Err(err) = glaze_err {
-                warn!("limited bonbon {}: failed to glaze: {:?}", mint_key, err);
-                continue;
-            }
+        let bonbon = match assemble_bonbon(
+            &mut psql_client, &select_partition_key, &updaters, mint_key, None, &mut timings)? {
+            Some(bonbon) => bonbon,
+            None => continue,
+        };
 
-            // and theoretically we should always have exactly 1 glazing at this point
-            if bonbon.glazings.is_empty() {
-                warn!("limited bonbon {}: no glazings", mint_key);
-            } else if bonbon.glazings.len() > 1 {
-                warn!("limited bonbon {}: too many glazings ({})", mint_key, bonbon.glazings.len());
-            }
-        }
+        let anomalies = bonbon::validate::validate(&bonbon);
+        let glazing_events = bonbon.glazing_events();
 
         // TODO: more verification on partition_keys?
         let query_start = std::time::Instant::now();
-        psql_client.query(
-            &insert_bonbon_statement,
+        owner_index.update(&mut psql_client, &bonbon)?;
+        collection_index.update(&mut psql_client, &bonbon)?;
+
+        writer.write(
+            bonbons_table,
             &[
                 &bonbon.metadata_key.to_string(),
                 &bonbon.mint_key.to_string(),
-                &bonbon.mint_authority.to_string(),
+                &bonbon.mint_authority.map(|k| k.to_string()),
+                &bonbon.freeze_authority.map(|k| k.to_string()),
                 &bonbon.current_owner.as_ref().map(|k| k.owner.to_string()),
                 &bonbon.current_owner.as_ref().map(|k| k.account.to_string()),
                 &convert::EditionStatus::from(bonbon.edition_status),
@@ -445,14 +490,30 @@ fn reassemble(config: &Config) -> Result<()> {
             ],
         )?;
 
+        for event in glazing_events {
+            let change = convert::GlazingChange::from(event.change);
+            writer.write(
+                glazing_changes_table,
+                &[
+                    &bonbon.metadata_key.to_string(),
+                    &convert::InstructionIndex::from(event.instruction_index),
+                    &change.kind,
+                    &change.address,
+                    &change.old_value,
+                    &change.new_value,
+                ],
+            )?;
+        }
+
         for glazing in bonbon.glazings {
-            psql_client.query(
-                &insert_glazing_statement,
+            writer.write(
+                glazings_table,
                 &[
                     &bonbon.metadata_key.to_string(),
                     &glazing.name.trim_matches(char::from(0)),
                     &glazing.symbol.trim_matches(char::from(0)),
                     &glazing.uri.trim_matches(char::from(0)),
+                    &glazing.seller_fee_basis_points,
                     &glazing.collection.as_ref().map(|c| c.address.to_string()),
                     &glazing.collection.as_ref().map(|c| c.verified),
                     &glazing.creators.get(0).map(convert::Creator::from),
@@ -461,16 +522,21 @@ fn reassemble(config: &Config) -> Result<()> {
                     &glazing.creators.get(3).map(convert::Creator::from),
                     &glazing.creators.get(4).map(convert::Creator::from),
                     &convert::InstructionIndex::from(glazing.instruction_index),
+                    &convert::GlazingSource::from(&glazing.source),
                 ],
             )?;
         }
 
         for transfer in bonbon.transfers {
-            psql_client.query(
-                &insert_transfer_statement,
+            writer.write(
+                transfers_table,
                 &[
                     &bonbon.mint_key.to_string(),
-                    &transfer.slot,
+                    &convert::InstructionIndex::from(transfer.instruction_index),
+                    &transfer.signature.to_string(),
+                    &convert::TransferKind::from(transfer.kind),
+                    &transfer.authority.to_string(),
+                    &transfer.delegated,
                     &transfer.start.as_ref().map(|t| t.owner.to_string()),
                     &transfer.start.as_ref().map(|t| t.account.to_string()),
                     &transfer.end.as_ref().map(|t| t.owner.to_string()),
@@ -479,16 +545,489 @@ fn reassemble(config: &Config) -> Result<()> {
             )?;
         };
 
+        for activity in bonbon.activities {
+            writer.write(
+                activities_table,
+                &[
+                    &activity.wallet.to_string(),
+                    &convert::InstructionIndex::from(activity.instruction_index),
+                    &convert::ActivityKind::from(activity.kind),
+                    &bonbon.mint_key.to_string(),
+                    &activity.counterparty.map(|k| k.to_string()),
+                    &activity.signature.to_string(),
+                ],
+            )?;
+        }
+
+        for change in bonbon.authority_changes {
+            writer.write(
+                authority_changes_table,
+                &[
+                    &bonbon.mint_key.to_string(),
+                    &convert::AuthorityKind::from(change.kind),
+                    &change.new_authority.map(|k| k.to_string()),
+                    &convert::InstructionIndex::from(change.instruction_index),
+                ],
+            )?;
+        }
+
+        for anomaly in anomalies {
+            debug!("bonbon {}: {:?}", bonbon.mint_key, anomaly);
+            let anomaly = convert::Anomaly::from(anomaly);
+            writer.write(
+                anomalies_table,
+                &[
+                    &bonbon.mint_key.to_string(),
+                    &anomaly.kind,
+                    &anomaly.instruction_index,
+                    &anomaly.details,
+                ],
+            )?;
+        }
+
+        batch_mint_keys.push(bonbon.mint_key.to_string());
+        if writer.is_full() {
+            flush_reassembled(&mut psql_client, &mut writer, &mut batch_mint_keys)?;
+        }
+
         update_queries += query_start.elapsed();
     }
+    flush_reassembled(&mut psql_client, &mut writer, &mut batch_mint_keys)?;
+    collection_index.flush(&mut psql_client)?;
+
     log::info!("reassembled in {:?}", loop_start.elapsed());
-    log::info!("partition queries took {:?}", partition_queries);
+    log::info!("partition queries took {:?}", timings.partition_queries);
     log::info!("update queries took {:?}", update_queries);
-    log::info!("deserialization marshalling took {:?}", deserialization_duration);
+    log::info!("deserialization marshalling took {:?}", timings.deserialization);
+
+    Ok(())
+}
+
+fn anomalies(
+    config: &Config,
+    kind: Option<String>,
+    limit: i64,
+) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let summary = psql_client.query(
+        "SELECT kind::VARCHAR, count(*), count(DISTINCT mint_key)
+         FROM anomalies
+         GROUP BY kind
+         ORDER BY kind
+        ",
+        &[],
+    )?;
+
+    println!("{:<32} {:>12} {:>12}", "kind", "anomalies", "bonbons");
+    for row in summary {
+        let kind: String = row.get(0);
+        let count: i64 = row.get(1);
+        let bonbons: i64 = row.get(2);
+        println!("{:<32} {:>12} {:>12}", kind, count, bonbons);
+    }
+
+    if let Some(kind) = kind {
+        let examples = psql_client.query(
+            "SELECT mint_key, (instruction_index).slot, details
+             FROM anomalies
+             WHERE kind::VARCHAR = $1
+             ORDER BY (instruction_index).slot DESC NULLS LAST
+             LIMIT $2
+            ",
+            &[&kind, &limit],
+        )?;
+
+        println!();
+        for row in examples {
+            let mint_key: String = row.get(0);
+            let slot: Option<i64> = row.get(1);
+            let details: Option<String> = row.get(2);
+            println!("{:<44} {:>12} {}",
+                     mint_key,
+                     slot.map(|s| s.to_string()).unwrap_or_default(),
+                     details.unwrap_or_default());
+        }
+    }
 
     Ok(())
 }
 
+fn collection(
+    config: &Config,
+    collection_key: String,
+) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let aggregate = collection::aggregate(&mut psql_client, &collection_key)?
+        .ok_or(anyhow!("No collection {}", collection_key))?;
+
+    println!("collection            {}", aggregate.collection_key);
+    println!("verified members      {}", aggregate.verified_members);
+    println!("unverified claimants  {}", aggregate.unverified_claimants);
+    println!("unique holders        {}", aggregate.unique_holders);
+    println!("total burns           {}", aggregate.total_burns);
+    println!("first mint slot       {}",
+             aggregate.first_mint_slot.map(|s| s.to_string()).unwrap_or_default());
+    println!("last mint slot        {}",
+             aggregate.last_mint_slot.map(|s| s.to_string()).unwrap_or_default());
+    println!("holder distribution   {}", aggregate.holder_distribution);
+
+    Ok(())
+}
+
+fn print_holdings(holdings: &[owner::Holding]) {
+    for holding in holdings {
+        println!("{:<44} {:<44} {:>12} {}",
+                 holding.mint_key,
+                 holding.account,
+                 holding.since.slot,
+                 match holding.collection_verified {
+                     Some(false) => "(unverified collection)",
+                     _ => "",
+                 });
+    }
+}
+
+fn owner(
+    config: &Config,
+    owner_key: String,
+    slot: Option<i64>,
+    by_collection: bool,
+) -> Result<()> {
+    let owner_key = owner_key.parse::<Pubkey>()
+        .map_err(|_| anyhow!("Invalid owner pubkey"))?;
+
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let holdings = match slot {
+        Some(slot) => owner::holdings_at(&mut psql_client, &owner_key, slot)?,
+        None => owner::holdings(&mut psql_client, &owner_key)?,
+    };
+
+    if by_collection {
+        for (collection_key, holdings) in owner::by_collection(holdings) {
+            println!("{} ({})",
+                     collection_key.as_deref().unwrap_or("no collection"), holdings.len());
+            print_holdings(&holdings);
+            println!();
+        }
+    } else {
+        print_holdings(&holdings);
+    }
+
+    Ok(())
+}
+
+fn resolve(
+    config: &Config,
+    fetcher: &dyn resolve::Fetcher,
+) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let resolve_start = std::time::Instant::now();
+    resolve::resolve_glazings(&mut psql_client, fetcher)?;
+    log::info!("resolved in {:?}", resolve_start.elapsed());
+
+    Ok(())
+}
+
+fn activity(
+    config: &Config,
+    wallet: String,
+    after: Option<bonbon::assemble::InstructionIndex>,
+    limit: i64,
+) -> Result<()> {
+    let wallet = wallet.parse::<Pubkey>()
+        .map_err(|_| anyhow!("Invalid wallet pubkey"))?;
+
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let records = activity::activities(
+        &mut psql_client, &wallet, after.map(convert::InstructionIndex::from), limit)?;
+
+    let format_index = |i: &convert::InstructionIndex|
+        bonbon::assemble::InstructionIndex::from(i.clone()).to_string();
+
+    for record in &records {
+        println!("{:<24} {:<16} {:<44} {:<44} {}",
+                 format_index(&record.instruction_index),
+                 record.kind,
+                 record.mint_key,
+                 record.counterparty.as_deref().unwrap_or(""),
+                 record.signature);
+    }
+
+    if records.len() as i64 == limit {
+        if let Some(last) = records.last() {
+            println!("\nnext page: --after {}", format_index(&last.instruction_index));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_instruction_index(s: &str) -> Result<bonbon::assemble::InstructionIndex> {
+    s.parse().map_err(|err: String| anyhow!(err))
+}
+
+fn parse_point_in_time(
+    slot: Option<&str>,
+    instruction_index: Option<&str>,
+) -> Result<bonbon::assemble::PointInTime> {
+    use bonbon::assemble::PointInTime;
+    match (slot, instruction_index) {
+        (Some(slot), None) => Ok(PointInTime::Slot(
+            slot.parse::<i64>().map_err(|_| anyhow!("Invalid --slot"))?)),
+        (None, Some(instruction_index)) => Ok(PointInTime::Instruction(
+            parse_instruction_index(instruction_index)?)),
+        _ => Err(anyhow!("Expected exactly one of --slot or --instruction_index")),
+    }
+}
+
+fn snapshot(
+    config: &Config,
+    mint_key: String,
+    at: bonbon::assemble::PointInTime,
+) -> Result<()> {
+    let mint_key = mint_key.parse::<Pubkey>()
+        .map_err(|_| anyhow!("Invalid --mint_key"))?;
+
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let select_partition_key = psql_client.prepare(SELECT_PARTITION_KEY)?;
+
+    let bonbon = assemble_bonbon(
+        &mut psql_client,
+        &select_partition_key,
+        &bonbon_updaters(),
+        mint_key,
+        Some(&at),
+        &mut AssembleTimings::default(),
+    )?.ok_or(anyhow!("No bonbon for {} at {:?}", mint_key, at))?;
+
+    println!("{}", serde_json::to_string_pretty(&bonbon)?);
+
+    Ok(())
+}
+
+// provenance graph of one bonbon or every verified member of a collection
+fn export(
+    config: &Config,
+    mint_key: Option<String>,
+    collection_key: Option<String>,
+    format: export::Format,
+    output: Option<String>,
+) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    let mint_keys = match (mint_key, collection_key) {
+        (Some(mint_key), None) => vec![mint_key],
+        (None, Some(collection_key)) => collection::members(&mut psql_client, &collection_key)?,
+        _ => return Err(anyhow!("Expected exactly one of --mint_key or --collection")),
+    };
+
+    let select_partition_key = psql_client.prepare(SELECT_PARTITION_KEY)?;
+    let updaters = bonbon_updaters();
+    let mut timings = AssembleTimings::default();
+
+    let mut graph = bonbon::graph::Graph::default();
+    for mint_key in mint_keys {
+        let mint_key = mint_key.parse::<Pubkey>()
+            .map_err(|_| anyhow!("Invalid mint key {}", mint_key))?;
+        match assemble_bonbon(
+            &mut psql_client,
+            &select_partition_key,
+            &updaters,
+            mint_key,
+            None,
+            &mut timings,
+        )? {
+            Some(bonbon) => graph.add_bonbon(&bonbon),
+            None => warn!("skipping {} in export", mint_key),
+        }
+    }
+
+    // prices need the full transactions, which are only there if they were stored
+    let signatures = graph.transfer_signatures()
+        .into_iter()
+        .map(|signature| signature.as_ref().to_vec())
+        .collect::<Vec<_>>();
+    let mut transactions = std::collections::HashMap::new();
+    for row in psql_client.query(
+        "SELECT signature, transaction FROM transactions WHERE signature = ANY($1)",
+        &[&signatures],
+    )? {
+        let signature = Signature::new(row.get::<_, Vec<u8>>(0).as_slice());
+        transactions.insert(signature, storage::decode_transaction(row.get(1))?);
+    }
+    graph.add_sale_lamports(&transactions);
+
+    info!("exporting {} nodes and {} edges", graph.nodes.len(), graph.edges.len());
+
+    match output {
+        Some(path) => {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            export::write(&graph, format, &mut file)?;
+        }
+        None => {
+            export::write(&graph, format, &mut std::io::stdout().lock())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn migrate_storage(config: &Config, batch_size: i64) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+
+    storage::migrate(&mut psql_client, batch_size)
+}
+
+fn ledger_source_args() -> Vec<clap::Arg<'static>> {
+    vec![
+        clap::Arg::new("source")
+            .long("source")
+            .value_name("bigtable|blockstore|json|protobuf|rpc")
+            .takes_value(true)
+            .default_value("bigtable")
+            .help("Where to read confirmed blocks from"),
+        clap::Arg::new("ledger_path")
+            .long("ledger_path")
+            .value_name("DIRPATH")
+            .takes_value(true)
+            .help("Ledger directory for blockstore, or directory of per-slot block dumps"),
+        clap::Arg::new("rpc")
+            .long("rpc")
+            .value_name("URL")
+            .takes_value(true)
+            .help("JSON-RPC endpoint to fetch blocks from. Implies --source rpc"),
+        clap::Arg::new("rpc_requests_per_sec")
+            .long("rpc_requests_per_sec")
+            .value_name("COUNT")
+            .takes_value(true)
+            .default_value("10")
+            .help("Maximum request rate against --rpc"),
+        clap::Arg::new("rpc_max_retries")
+            .long("rpc_max_retries")
+            .value_name("COUNT")
+            .takes_value(true)
+            .default_value("5")
+            .help("Retries per request for rate limits and transient errors"),
+        clap::Arg::new("rpc_timeout_secs")
+            .long("rpc_timeout_secs")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .default_value("60")
+            .help("Timeout per request against --rpc"),
+        clap::Arg::new("max_supported_transaction_version")
+            .long("max_supported_transaction_version")
+            .value_name("VERSION")
+            .takes_value(true)
+            .default_value("0")
+            .help("Highest transaction version to request from --rpc"),
+        clap::Arg::new("bigtable_path")
+            .long("bigtable_path")
+            .value_name("FILEPATH")
+            .takes_value(true)
+            .global(true)
+            .help("Path to bigtable credentials JSON"),
+        clap::Arg::new("bigtable_emulator_host")
+            .long("bigtable_emulator_host")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .help("Fetch from a bigtable emulator instead. No credentials needed"),
+        clap::Arg::new("bigtable_instance")
+            .long("bigtable_instance")
+            .value_name("INSTANCE_NAME")
+            .takes_value(true)
+            .help("Bigtable instance to read from [default: solana-ledger]"),
+        clap::Arg::new("bigtable_app_profile")
+            .long("bigtable_app_profile")
+            .value_name("APP_PROFILE_ID")
+            .takes_value(true)
+            .help("Bigtable app profile to route requests through [default: default]"),
+        clap::Arg::new("bigtable_timeout_secs")
+            .long("bigtable_timeout_secs")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .help("Timeout per bigtable request"),
+    ]
+}
+
+fn fetch_pipeline_args() -> Vec<clap::Arg<'static>> {
+    vec![
+        clap::Arg::new("chunk_size")
+            .long("chunk_size")
+            .value_name("SLOTS")
+            .takes_value(true)
+            .default_value("16")
+            .help("Slots per block request"),
+        clap::Arg::new("concurrency")
+            .long("concurrency")
+            .value_name("COUNT")
+            .takes_value(true)
+            .default_value("4")
+            .help("Block requests in flight at once"),
+        clap::Arg::new("decode_threads")
+            .long("decode_threads")
+            .value_name("COUNT")
+            .takes_value(true)
+            .default_value("4")
+            .help("Threads decoding and filtering fetched blocks"),
+        clap::Arg::new("in_flight")
+            .long("in_flight")
+            .value_name("BLOCKS")
+            .takes_value(true)
+            .default_value("256")
+            .help("Blocks queued between pipeline stages before fetching waits"),
+    ]
+}
+
+fn filter_args() -> Vec<clap::Arg<'static>> {
+    vec![
+        clap::Arg::new("filter_config")
+            .long("filter_config")
+            .value_name("FILEPATH")
+            .takes_value(true)
+            .help("TOML filter spec. The flags below override it"),
+        clap::Arg::new("program")
+            .long("program")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .help("Keep transactions that reference this program \
+                   [default: token and token metadata]"),
+        clap::Arg::new("account")
+            .long("account")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .help("Only keep transactions that also reference one of these accounts"),
+        clap::Arg::new("include_failed")
+            .long("include_failed")
+            .help("Keep failed transactions too"),
+        clap::Arg::new("min_slot")
+            .long("min_slot")
+            .value_name("SLOT")
+            .takes_value(true)
+            .help("Skip slots before this"),
+        clap::Arg::new("max_slot")
+            .long("max_slot")
+            .value_name("SLOT")
+            .takes_value(true)
+            .help("Skip slots after this"),
+    ]
+}
+
 fn main() -> Result<()> {
     let log_file_default = "bonbon.log";
 
@@ -512,34 +1051,312 @@ fn main() -> Result<()> {
                 .global(true)
                 .help("Transaction DB connection configuration")
         )
+        .arg(
+            clap::Arg::new("batch_size")
+                .long("batch_size")
+                .value_name("ROWS")
+                .takes_value(true)
+                .default_value("10000")
+                .global(true)
+                .help("Rows buffered before each COPY into the DB")
+        )
         .subcommand(
             clap::Command::new("fetch")
             .about("Fetch transactions into DB")
+            .args(ledger_source_args())
             .arg(
-                clap::Arg::new("bigtable_path")
-                    .long("bigtable_path")
+                clap::Arg::new("block_range")
+                    .long("block_range")
                     .value_name("FILEPATH")
                     .takes_value(true)
                     .global(true)
-                    .help("Path to bigtable credentials JSON")
+                    .help("Block range to fetch")
             )
+            .args(fetch_pipeline_args())
+            .args(filter_args())
+        )
+        .subcommand(
+            clap::Command::new("ingest")
+            .about("Fetch and partition transactions without going through the transactions table")
             .arg(
                 clap::Arg::new("block_range")
                     .long("block_range")
-                    .value_name("FILEPATH")
+                    .value_name("START-END")
                     .takes_value(true)
-                    .global(true)
-                    .help("Block range to fetch")
+                    .required(true)
+                    .help("Block range to ingest, as for fetch")
+            )
+            .arg(
+                clap::Arg::new("store_transactions")
+                    .long("store_transactions")
+                    .help("Also write the raw transactions, as fetch does")
             )
+            .args(ledger_source_args())
+            .args(fetch_pipeline_args())
+            .args(filter_args())
+        )
+        .subcommand(
+            clap::Command::new("coverage")
+            .about("Report which slots in a range fetch has covered")
+            .after_help("The report comes from the checkpoints fetch and ingest record as they \
+                         commit, not from the rows. A slot is unfetched when no checkpoint for \
+                         the filter covers it, and failed when the ledger listed it as a block \
+                         but fetch couldn't get it. Rows deleted after they were checkpointed, or \
+                         blocks the ledger only listed later, aren't detected; the transaction \
+                         count is for reference only")
+            .arg(
+                clap::Arg::new("block_range")
+                    .long("block_range")
+                    .value_name("START-END")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Block range to check, as for fetch")
+            )
+            .arg(
+                clap::Arg::new("refill")
+                    .long("refill")
+                    .help("Fetch the unfetched and failed slots")
+            )
+            .arg(
+                clap::Arg::new("ingest")
+                    .long("ingest")
+                    .help("Check what ingest has partitioned instead of what fetch has stored, \
+                           and refill with ingest")
+            )
+            .arg(
+                clap::Arg::new("store_transactions")
+                    .long("store_transactions")
+                    .requires("ingest")
+                    .help("Also write the raw transactions when refilling with ingest")
+            )
+            .args(ledger_source_args())
+            .args(fetch_pipeline_args())
+            .args(filter_args())
         )
         .subcommand(
             clap::Command::new("partition")
-            .about("Partition all transactions found in the DB")
+            .about("Partition transactions added to the DB since the last run")
+            .arg(
+                clap::Arg::new("workers")
+                    .long("workers")
+                    .value_name("COUNT")
+                    .takes_value(true)
+                    .default_value("1")
+                    .help("Threads partitioning slot ranges in parallel")
+            )
+            .arg(
+                clap::Arg::new("from")
+                    .long("from")
+                    .value_name("SLOT")
+                    .takes_value(true)
+                    .help("Re-partition from this slot [default: first transaction]")
+            )
+            .arg(
+                clap::Arg::new("to")
+                    .long("to")
+                    .value_name("SLOT")
+                    .takes_value(true)
+                    .help("Re-partition up to and including this slot [default: last transaction]")
+            )
+            .arg(
+                clap::Arg::new("slot_range")
+                    .long("slot_range")
+                    .value_name("START-END")
+                    .takes_value(true)
+                    .conflicts_with_all(&["from", "to"])
+                    .help("Re-partition these slots, as --from START --to END-1")
+            )
+        )
+        .subcommand(
+            clap::Command::new("migrate_storage")
+            .about("Rewrite stored transactions in the current storage format")
+            .after_help("--batch_size is transactions rewritten per DB transaction, 1000 unless given")
         )
         .subcommand(
             clap::Command::new("reassemble")
             .about("Reassemble all partitioned keys found in the DB")
         )
+        .subcommand(
+            clap::Command::new("anomalies")
+            .about("Report anomalies found while reassembling")
+            .arg(
+                clap::Arg::new("kind")
+                    .long("kind")
+                    .value_name("ANOMALY_KIND")
+                    .takes_value(true)
+                    .help("List the latest bonbons with this anomaly")
+            )
+            .arg(
+                clap::Arg::new("limit")
+                    .long("limit")
+                    .value_name("COUNT")
+                    .takes_value(true)
+                    .default_value("20")
+                    .help("Number of bonbons to list with --kind")
+            )
+        )
+        .subcommand(
+            clap::Command::new("activity")
+            .about("Page through a wallet's activity")
+            .arg(
+                clap::Arg::new("wallet")
+                    .index(1)
+                    .value_name("PUBKEY")
+                    .required(true)
+                    .help("Wallet to list activity for")
+            )
+            .arg(
+                clap::Arg::new("after")
+                    .long("after")
+                    .value_name("SLOT.BLOCK_INDEX.OUTER_INDEX[.INNER_INDEX]")
+                    .takes_value(true)
+                    .help("Start after this instruction")
+            )
+            .arg(
+                clap::Arg::new("limit")
+                    .long("limit")
+                    .value_name("COUNT")
+                    .takes_value(true)
+                    .default_value("50")
+                    .help("Page size")
+            )
+        )
+        .subcommand(
+            clap::Command::new("collection")
+            .about("Show the aggregate for a collection")
+            .arg(
+                clap::Arg::new("collection_key")
+                    .index(1)
+                    .value_name("PUBKEY")
+                    .required(true)
+                    .help("Collection mint")
+            )
+        )
+        .subcommand(
+            clap::Command::new("export")
+            .about("Export the ownership history of a bonbon or collection as a graph")
+            .after_help("Transfers between wallets are labelled with the lamports the new owner \
+                         paid the previous one, when their transactions are in `transactions` and \
+                         the payment shows in the balances")
+            .arg(
+                clap::Arg::new("mint_key")
+                    .long("mint_key")
+                    .value_name("PUBKEY")
+                    .takes_value(true)
+                    .help("Mint of the bonbon to export")
+            )
+            .arg(
+                clap::Arg::new("collection")
+                    .long("collection")
+                    .value_name("PUBKEY")
+                    .takes_value(true)
+                    .help("Export every verified member of this collection")
+            )
+            .arg(
+                clap::Arg::new("format")
+                    .long("format")
+                    .value_name("graphml|dot|json")
+                    .takes_value(true)
+                    .default_value("graphml")
+                    .help("Output format")
+            )
+            .arg(
+                clap::Arg::new("output")
+                    .long("output")
+                    .value_name("FILEPATH")
+                    .takes_value(true)
+                    .help("Write to this file instead of stdout")
+            )
+        )
+        .subcommand(
+            clap::Command::new("owner")
+            .about("List the bonbons held by a wallet")
+            .arg(
+                clap::Arg::new("owner")
+                    .index(1)
+                    .value_name("PUBKEY")
+                    .required(true)
+                    .help("Wallet to list holdings for")
+            )
+            .arg(
+                clap::Arg::new("slot")
+                    .long("slot")
+                    .value_name("SLOT")
+                    .takes_value(true)
+                    .help("List holdings as of this slot instead of now")
+            )
+            .arg(
+                clap::Arg::new("by_collection")
+                    .long("by_collection")
+                    .help("Group holdings by collection")
+            )
+        )
+        .subcommand(
+            clap::Command::new("resolve")
+            .about("Fetch and validate the off-chain metadata for every glazing in the DB")
+            .arg(
+                clap::Arg::new("fetcher")
+                    .long("fetcher")
+                    .value_name("http|local")
+                    .takes_value(true)
+                    .default_value("http")
+                    .help("Where to fetch metadata documents from")
+            )
+            .arg(
+                clap::Arg::new("local_dir")
+                    .long("local_dir")
+                    .value_name("DIRPATH")
+                    .takes_value(true)
+                    .help("Directory of documents for the local fetcher")
+            )
+            .arg(
+                clap::Arg::new("arweave_gateway")
+                    .long("arweave_gateway")
+                    .value_name("URL")
+                    .takes_value(true)
+                    .help("Gateway for ar:// uris")
+            )
+            .arg(
+                clap::Arg::new("ipfs_gateway")
+                    .long("ipfs_gateway")
+                    .value_name("URL")
+                    .takes_value(true)
+                    .help("Gateway for ipfs:// uris")
+            )
+            .arg(
+                clap::Arg::new("timeout_secs")
+                    .long("timeout_secs")
+                    .value_name("SECONDS")
+                    .takes_value(true)
+                    .default_value("30")
+                    .help("Timeout per request for the http fetcher")
+            )
+        )
+        .subcommand(
+            clap::Command::new("snapshot")
+            .about("Reassemble a single bonbon as it was at some slot or instruction")
+            .arg(
+                clap::Arg::new("mint_key")
+                    .long("mint_key")
+                    .value_name("PUBKEY")
+                    .takes_value(true)
+                    .help("Mint of the bonbon to reassemble")
+            )
+            .arg(
+                clap::Arg::new("slot")
+                    .long("slot")
+                    .value_name("SLOT")
+                    .takes_value(true)
+                    .help("Include every instruction up to and including this slot")
+            )
+            .arg(
+                clap::Arg::new("instruction_index")
+                    .long("instruction_index")
+                    .value_name("SLOT.BLOCK_INDEX.OUTER_INDEX[.INNER_INDEX]")
+                    .takes_value(true)
+                    .help("Include every instruction up to and including this one")
+            )
+        )
         .get_matches();
 
     let config = Config {
@@ -551,6 +1368,11 @@ fn main() -> Result<()> {
             .value_of("log_file")
             .unwrap()
             .to_string(),
+        batch_size: matches
+            .value_of("batch_size")
+            .unwrap()
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Invalid --batch_size"))?,
     };
 
     fern::Dispatch::new()
@@ -578,28 +1400,175 @@ fn main() -> Result<()> {
     debug!("subcommand: {:?}", matches.subcommand());
     debug!("config: {:?}", config);
 
+    // before any runtime or worker threads exist
+    if let Some(("fetch" | "ingest" | "coverage", sub_m)) = matches.subcommand() {
+        if let Some(emulator_host) = sub_m.value_of("bigtable_emulator_host") {
+            ledger::set_emulator_env(emulator_host);
+        }
+    }
+
     match matches.subcommand() {
         Some(("fetch", sub_m)) => {
-            tokio::runtime::Builder::new_current_thread()
+            let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()
-                .unwrap()
-                .block_on(async {
-                    fetch(
-                        &config,
-                        sub_m.value_of("bigtable_path")
-                            .ok_or(anyhow!("Missing --bigtable_path"))?.to_string(),
-                        sub_m.value_of("block_range")
-                            .ok_or(anyhow!("Missing --block_range"))?.to_string(),
-                    ).await
-                })?
-        }
-        Some(("partition", _)) => {
-            partition(&config)?;
+                .unwrap();
+            let source = ledger_source(&rt, sub_m)?;
+            fetch(
+                &config,
+                &rt,
+                source.as_ref(),
+                sub_m.value_of("block_range")
+                    .ok_or(anyhow!("Missing --block_range"))?.to_string(),
+                fetch_options(&config, sub_m)?,
+            )?;
+        }
+        Some(("ingest", sub_m)) => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let source = ledger_source(&rt, sub_m)?;
+            fetch(
+                &config,
+                &rt,
+                source.as_ref(),
+                sub_m.value_of("block_range").unwrap().to_string(),
+                fetch::FetchOptions {
+                    store_transactions: sub_m.is_present("store_transactions"),
+                    partition: true,
+                    ..fetch_options(&config, sub_m)?
+                },
+            )?;
+        }
+        Some(("coverage", sub_m)) => {
+            let block_range = sub_m.value_of("block_range").unwrap().to_string();
+            let mut options = fetch_options(&config, sub_m)?;
+            if sub_m.is_present("ingest") {
+                options.store_transactions = sub_m.is_present("store_transactions");
+                options.partition = true;
+            }
+            if sub_m.is_present("refill") {
+                let rt = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                let source = ledger_source(&rt, sub_m)?;
+                coverage(&config, block_range, options, Some((&rt, source.as_ref())))?;
+            } else {
+                coverage(&config, block_range, options, None)?;
+            }
+        }
+        Some(("partition", sub_m)) => {
+            let parse_slot = |name: &str| sub_m.value_of(name)
+                .map(|s| s.parse::<Slot>().map_err(|_| anyhow!("Invalid --{}", name)))
+                .transpose();
+            let (from, to) = match sub_m.value_of("slot_range") {
+                Some(slot_range) => {
+                    let (start, end) = parse_block_range(slot_range)
+                        .map_err(|_| anyhow!("Invalid --slot_range"))?;
+                    if start == end {
+                        return Ok(());
+                    }
+                    (Some(start), Some(end - 1))
+                }
+                None => (parse_slot("from")?, parse_slot("to")?),
+            };
+            partition(
+                &config,
+                from,
+                to,
+                sub_m.value_of("workers").unwrap()
+                    .parse::<usize>().map_err(|_| anyhow!("Invalid --workers"))?,
+            )?;
+        }
+        Some(("migrate_storage", sub_m)) => {
+            // each row is decoded and re-encoded, so this keeps the smaller default it had before
+            // --batch_size was global
+            let batch_size = match sub_m.occurrences_of("batch_size") {
+                0 => 1000,
+                _ => config.batch_size as i64,
+            };
+            migrate_storage(&config, batch_size)?;
         }
         Some(("reassemble", _)) => {
             reassemble(&config)?;
         }
+        Some(("anomalies", sub_m)) => {
+            anomalies(
+                &config,
+                sub_m.value_of("kind").map(|k| k.to_string()),
+                sub_m.value_of("limit").unwrap()
+                    .parse::<i64>().map_err(|_| anyhow!("Invalid --limit"))?,
+            )?;
+        }
+        Some(("activity", sub_m)) => {
+            activity(
+                &config,
+                sub_m.value_of("wallet").unwrap().to_string(),
+                sub_m.value_of("after").map(parse_instruction_index).transpose()?,
+                sub_m.value_of("limit").unwrap()
+                    .parse::<i64>().map_err(|_| anyhow!("Invalid --limit"))?,
+            )?;
+        }
+        Some(("collection", sub_m)) => {
+            collection(
+                &config,
+                sub_m.value_of("collection_key").unwrap().to_string(),
+            )?;
+        }
+        Some(("export", sub_m)) => {
+            export(
+                &config,
+                sub_m.value_of("mint_key").map(|k| k.to_string()),
+                sub_m.value_of("collection").map(|k| k.to_string()),
+                sub_m.value_of("format").unwrap().parse()?,
+                sub_m.value_of("output").map(|p| p.to_string()),
+            )?;
+        }
+        Some(("owner", sub_m)) => {
+            owner(
+                &config,
+                sub_m.value_of("owner").unwrap().to_string(),
+                sub_m.value_of("slot")
+                    .map(|s| s.parse::<i64>().map_err(|_| anyhow!("Invalid --slot")))
+                    .transpose()?,
+                sub_m.is_present("by_collection"),
+            )?;
+        }
+        Some(("resolve", sub_m)) => {
+            let fetcher: Box<dyn resolve::Fetcher> = match sub_m.value_of("fetcher").unwrap() {
+                "http" => {
+                    let mut gateways = resolve::Gateways::default();
+                    if let Some(arweave) = sub_m.value_of("arweave_gateway") {
+                        gateways.arweave = arweave.to_string();
+                    }
+                    if let Some(ipfs) = sub_m.value_of("ipfs_gateway") {
+                        gateways.ipfs = ipfs.to_string();
+                    }
+                    let timeout = sub_m.value_of("timeout_secs").unwrap()
+                        .parse::<u64>().map_err(|_| anyhow!("Invalid --timeout_secs"))?;
+                    Box::new(resolve::HttpFetcher::new(
+                        gateways, std::time::Duration::from_secs(timeout))?)
+                }
+                "local" => Box::new(resolve::LocalFetcher::new(
+                    sub_m.value_of("local_dir")
+                        .ok_or(anyhow!("Missing --local_dir"))?.into())),
+                other => return Err(anyhow!("Unknown --fetcher {}", other)),
+            };
+            resolve(&config, fetcher.as_ref())?;
+        }
+        Some(("snapshot", sub_m)) => {
+            snapshot(
+                &config,
+                sub_m.value_of("mint_key")
+                    .ok_or(anyhow!("Missing --mint_key"))?.to_string(),
+                parse_point_in_time(
+                    sub_m.value_of("slot"),
+                    sub_m.value_of("instruction_index"),
+                )?,
+            )?;
+        }
         o => {
             warn!("No matching subcommand found {:?}", o);
         }
@@ -0,0 +1,314 @@
+use {
+    crate::convert,
+    anyhow::Result,
+    bonbon::assemble::{replay_until, Bonbon, InstructionIndex, PointInTime},
+    solana_sdk::pubkey::Pubkey,
+    std::collections::{BTreeMap, HashMap},
+};
+
+#[derive(Debug, Clone)]
+pub struct Holding {
+    pub mint_key: String,
+
+    pub account: String,
+
+    // from the latest glazing
+    pub collection_key: Option<String>,
+
+    pub collection_verified: Option<bool>,
+
+    // the transfer that gave the owner this bonbon
+    pub since: convert::InstructionIndex,
+}
+
+impl Holding {
+    fn from_row(row: &postgres::Row) -> Self {
+        Self {
+            mint_key: row.get(0),
+            account: row.get(1),
+            collection_key: row.get(2),
+            collection_verified: row.get(3),
+            since: row.get(4),
+        }
+    }
+}
+
+// who held a bonbon at `at` (after all of its transfers if None) and the transfer that gave it to
+// them. `transfers` are each transfer's end ownership and index, in the order they were applied
+// like `Bonbon::transfers`. delegated transfers move the token like any other and after a burn
+// nobody holds it
+pub fn owner_at<'a, T, I>(
+    transfers: I,
+    at: Option<&'a PointInTime>,
+) -> Option<(T, InstructionIndex)>
+where
+    T: 'a,
+    I: IntoIterator<Item = (Option<T>, InstructionIndex)>,
+    I::IntoIter: 'a,
+{
+    let (end, instruction_index) = replay_until(transfers, at).last()?;
+    end.map(|end| (end, instruction_index))
+}
+
+// current holdings per owner. kept up to date by reassemble, one row per mint
+pub struct OwnerIndex {
+    upsert_statement: postgres::Statement,
+
+    delete_statement: postgres::Statement,
+}
+
+impl OwnerIndex {
+    pub fn new(psql_client: &mut postgres::Client) -> Result<Self> {
+        Ok(Self {
+            upsert_statement: psql_client.prepare(
+                "INSERT INTO holdings VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (mint_key) DO UPDATE SET
+                    owner = EXCLUDED.owner,
+                    account = EXCLUDED.account,
+                    collection_key = EXCLUDED.collection_key,
+                    collection_verified = EXCLUDED.collection_verified,
+                    since = EXCLUDED.since
+                ",
+            )?,
+            delete_statement: psql_client.prepare(
+                "DELETE FROM holdings WHERE mint_key = $1"
+            )?,
+        })
+    }
+
+    pub fn update(&self, psql_client: &mut postgres::Client, bonbon: &Bonbon) -> Result<()> {
+        let mint_key = bonbon.mint_key.to_string();
+        let transfers = bonbon.transfers.iter()
+            .map(|t| (t.end.as_ref(), t.instruction_index.clone()));
+        match owner_at(transfers, None) {
+            Some((current_owner, since)) => {
+                let collection = bonbon.collection();
+                psql_client.query(
+                    &self.upsert_statement,
+                    &[
+                        &mint_key,
+                        &current_owner.owner.to_string(),
+                        &current_owner.account.to_string(),
+                        &collection.map(|c| c.address.to_string()),
+                        &collection.map(|c| c.verified),
+                        &convert::InstructionIndex::from(since),
+                    ],
+                )?;
+            }
+            // burned (or never minted)
+            None => {
+                psql_client.query(&self.delete_statement, &[&mint_key])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn holdings(
+    psql_client: &mut postgres::Client,
+    owner: &Pubkey,
+) -> Result<Vec<Holding>> {
+    Ok(psql_client
+        .query(
+            "SELECT mint_key, account, collection_key, collection_verified, since
+             FROM holdings
+             WHERE owner = $1
+             ORDER BY since
+            ",
+            &[&owner.to_string()],
+        )?
+        .iter()
+        .map(Holding::from_row)
+        .collect())
+}
+
+// holdings as of the end of `slot`, from the transfer history rather than the current index.
+// collection info is from the latest glazing as of the slot too
+pub fn holdings_at(
+    psql_client: &mut postgres::Client,
+    owner: &Pubkey,
+    slot: i64,
+) -> Result<Vec<Holding>> {
+    let owner = owner.to_string();
+
+    // every transfer up to the slot of anything the owner had held by then
+    let mut transfers = HashMap::<String, Vec<_>>::new();
+    for row in psql_client.query(
+        "SELECT mint_key, end_owner, end_account, instruction_index
+         FROM transfers
+         WHERE (instruction_index).slot <= $2
+           AND mint_key IN (
+              SELECT mint_key FROM transfers
+              WHERE end_owner = $1 AND (instruction_index).slot <= $2
+           )
+         ORDER BY mint_key, instruction_index
+        ",
+        &[&owner, &slot],
+    )? {
+        let end = match (row.get::<_, Option<String>>(1), row.get::<_, Option<String>>(2)) {
+            (Some(end_owner), Some(end_account)) => Some((end_owner, end_account)),
+            _ => None,
+        };
+        let instruction_index = row.get::<_, convert::InstructionIndex>(3);
+        transfers
+            .entry(row.get(0))
+            .or_default()
+            .push((end, InstructionIndex::from(instruction_index)));
+    }
+
+    let at = PointInTime::Slot(slot);
+    let mut held = transfers
+        .into_iter()
+        .filter_map(|(mint_key, transfers)| match owner_at(transfers, Some(&at)) {
+            Some(((end_owner, account), since)) if end_owner == owner => {
+                Some((mint_key, account, since))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    held.sort_by(|a, b| a.2.cmp(&b.2));
+
+    let mint_keys = held.iter().map(|(mint_key, _, _)| mint_key.as_str()).collect::<Vec<_>>();
+    let collections = psql_client
+        .query(
+            "SELECT b.mint_key, g.collection_key, g.collection_verified
+             FROM bonbons b
+             JOIN LATERAL (
+                SELECT collection_key, collection_verified
+                FROM glazings
+                WHERE metadata_key = b.metadata_key AND (instruction_index).slot <= $2
+                ORDER BY instruction_index DESC
+                LIMIT 1
+             ) g ON true
+             WHERE b.mint_key = ANY($1)
+            ",
+            &[&mint_keys, &slot],
+        )?
+        .iter()
+        .map(|row| {
+            let collection: (Option<String>, Option<bool>) = (row.get(1), row.get(2));
+            (row.get::<_, String>(0), collection)
+        })
+        .collect::<HashMap<_, _>>();
+
+    Ok(held
+        .into_iter()
+        .map(|(mint_key, account, since)| {
+            let (collection_key, collection_verified) =
+                collections.get(&mint_key).cloned().unwrap_or((None, None));
+            Holding {
+                mint_key,
+                account,
+                collection_key,
+                collection_verified,
+                since: convert::InstructionIndex::from(since),
+            }
+        })
+        .collect())
+}
+
+// keyed by collection. unverified collections are grouped with verified ones of the same key,
+// check `collection_verified` on each holding
+pub fn by_collection(holdings: Vec<Holding>) -> BTreeMap<Option<String>, Vec<Holding>> {
+    let mut grouped = BTreeMap::<_, Vec<_>>::new();
+    for holding in holdings {
+        grouped
+            .entry(holding.collection_key.clone())
+            .or_default()
+            .push(holding);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bonbon::assemble::{Ownership, Transfer, TransferKind},
+        solana_sdk::signature::Signature,
+    };
+
+    fn key(n: u8) -> Pubkey {
+        Pubkey::new_from_array([n; 32])
+    }
+
+    fn index(slot: i64, inner_index: Option<i64>) -> InstructionIndex {
+        InstructionIndex { slot, block_index: 0, outer_index: 0, inner_index }
+    }
+
+    fn transfer(
+        instruction_index: InstructionIndex,
+        kind: TransferKind,
+        delegated: bool,
+        owner: Option<u8>,
+    ) -> Transfer {
+        Transfer {
+            instruction_index,
+            signature: Signature::default(),
+            kind,
+            authority: key(0),
+            delegated,
+            start: None,
+            end: owner.map(|n| Ownership { owner: key(n), account: key(n + 100) }),
+        }
+    }
+
+    // minted to 1 in slot 10, sold to 2 through a delegate in slot 20, burned in slot 30 by the
+    // inner Burn of a BurnNft
+    fn transfers() -> Vec<Transfer> {
+        vec![
+            transfer(index(10, None), TransferKind::Mint, false, Some(1)),
+            transfer(index(20, None), TransferKind::Transfer, true, Some(2)),
+            transfer(index(30, Some(0)), TransferKind::Burn, false, None),
+            transfer(index(30, None), TransferKind::BurnNft, false, None),
+        ]
+    }
+
+    fn owner(transfers: &[Transfer], at: Option<&PointInTime>) -> Option<(Pubkey, i64)> {
+        let transfers = transfers.iter().map(|t| (t.end.as_ref(), t.instruction_index.clone()));
+        owner_at(transfers, at).map(|(end, since)| (end.owner, since.slot))
+    }
+
+    #[test]
+    fn before_the_mint() {
+        assert_eq!(owner(&transfers(), Some(&PointInTime::Slot(9))), None);
+    }
+
+    #[test]
+    fn minted() {
+        assert_eq!(owner(&transfers(), Some(&PointInTime::Slot(19))), Some((key(1), 10)));
+    }
+
+    #[test]
+    fn delegated_transfer_moves_the_token() {
+        assert_eq!(owner(&transfers(), Some(&PointInTime::Slot(29))), Some((key(2), 20)));
+    }
+
+    #[test]
+    fn at_a_transfer_includes_it() {
+        let at = PointInTime::Instruction(index(20, None));
+        assert_eq!(owner(&transfers(), Some(&at)), Some((key(2), 20)));
+
+        let before = PointInTime::Instruction(index(19, Some(5)));
+        assert_eq!(owner(&transfers(), Some(&before)), Some((key(1), 10)));
+    }
+
+    #[test]
+    fn at_the_burn_nobody_holds_it() {
+        let at = PointInTime::Instruction(index(30, Some(0)));
+        assert_eq!(owner(&transfers(), Some(&at)), None);
+    }
+
+    #[test]
+    fn burned() {
+        assert_eq!(owner(&transfers(), Some(&PointInTime::Slot(30))), None);
+        assert_eq!(owner(&transfers(), None), None);
+    }
+
+    #[test]
+    fn current_owner() {
+        let mut transfers = transfers();
+        transfers.truncate(2);
+        assert_eq!(owner(&transfers, None), Some((key(2), 20)));
+    }
+}
@@ -0,0 +1,570 @@
+use {
+    crate::{convert, storage, writer},
+    anyhow::{Result, anyhow},
+    bonbon::partition::*,
+    log::*,
+    postgres::fallible_iterator::FallibleIterator,
+    solana_sdk::pubkey::Pubkey,
+    solana_transaction_status::TransactionWithStatusMeta,
+    std::{collections::{BTreeMap, HashSet}, sync::{Arc, Mutex}},
+};
+
+pub fn partitioners() -> [InstructionPartitioner; 2] {
+    [
+        InstructionPartitioner {
+            partitioner: partition_token_instruction,
+            program_id: spl_token::id(),
+        },
+        InstructionPartitioner {
+            partitioner: partition_metadata_instruction,
+            program_id: mpl_token_metadata::id(),
+        },
+    ]
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InstructionCounts {
+    pub partitioned: u64,
+
+    // no partitioner for the program
+    pub ignored: u64,
+
+    // the partitioner decided the instruction isn't NFT related
+    pub returned_none: u64,
+
+    pub failed: u64,
+}
+
+// what partitioning did with every instruction, by program and instruction variant (see
+// `bonbon::partition::instruction_variant`)
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    // including failed transactions, which are skipped
+    pub transactions: u64,
+
+    // partition_transaction failed outright, before any instruction
+    pub failed_transactions: u64,
+
+    pub instructions: BTreeMap<(Pubkey, Option<&'static str>), InstructionCounts>,
+}
+
+impl Stats {
+    fn counts(&mut self, program_key: &Pubkey, data: &[u8]) -> &mut InstructionCounts {
+        self.instructions
+            .entry((*program_key, instruction_variant(program_key, data)))
+            .or_default()
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        self.transactions += other.transactions;
+        self.failed_transactions += other.failed_transactions;
+        for (key, counts) in other.instructions {
+            let total = self.instructions.entry(key).or_default();
+            total.partitioned += counts.partitioned;
+            total.ignored += counts.ignored;
+            total.returned_none += counts.returned_none;
+            total.failed += counts.failed;
+        }
+    }
+}
+
+// partitions transactions into `partitions`, `partition_failures` and `account_keys`. shared by
+// the partition command and anything that partitions as it goes
+pub struct PartitionWriter {
+    partitioners: [InstructionPartitioner; 2],
+
+    writer: writer::CopyWriter,
+
+    partitions_table: writer::TableId,
+
+    partition_failures_table: writer::TableId,
+
+    account_keys_table: writer::TableId,
+
+    pub stats: Stats,
+}
+
+impl PartitionWriter {
+    pub fn new(psql_client: &mut postgres::Client, batch_size: usize) -> Result<Self> {
+        let mut writer = writer::CopyWriter::new(batch_size);
+        Ok(Self {
+            partitioners: partitioners(),
+            partitions_table: writer.add_table(psql_client, "partitions")?,
+            partition_failures_table: writer.add_table(psql_client, "partition_failures")?,
+            account_keys_table: writer.add_table(psql_client, "account_keys")?,
+            writer,
+            stats: Stats::default(),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        slot: i64,
+        block_index: i64,
+        signature: &[u8],
+        transaction: TransactionWithStatusMeta,
+    ) -> Result<()> {
+        self.stats.transactions += 1;
+
+        // skip errors
+        if transaction.get_status_meta().map(|m| m.status.is_err()) == Some(true) {
+            return Ok(());
+        }
+
+        let account_keys = transaction.account_keys()
+            .iter().map(|k| k.as_ref().to_vec()).collect::<Vec<_>>();
+
+        match partition_transaction(transaction, &self.partitioners) {
+            Ok(Partitions { partitioned, token_metas, other }) => {
+                if partitioned.len() != 0 {
+                    self.writer.write(
+                        self.account_keys_table,
+                        &[
+                            &signature,
+                            &account_keys,
+                            &token_metas.into_iter()
+                                .map(|m| convert::TransactionTokenMeta::from(m))
+                                .collect::<Vec<_>>(),
+                        ],
+                    )?;
+                }
+                for PartitionedInstruction {
+                    instruction,
+                    partition_key,
+                    program_key,
+                    outer_index,
+                    inner_index,
+                } in partitioned {
+                    self.stats.counts(&program_key, &instruction.data).partitioned += 1;
+                    // TODO: soft error?
+                    let serialized = bincode::serialize(&instruction)?;
+                    self.writer.write(
+                        self.partitions_table,
+                        &[
+                            &partition_key.as_ref(),
+                            &program_key.as_ref(),
+                            &slot,
+                            &block_index,
+                            &outer_index,
+                            &inner_index,
+                            &signature,
+                            &serialized,
+                        ],
+                    )?;
+                }
+
+                for OtherInstruction {
+                    reason,
+                    instruction,
+                    program_key,
+                    outer_index,
+                    inner_index,
+                } in other {
+                    let counts = self.stats.counts(&program_key, &instruction.data);
+                    match reason {
+                        Reason::PartitionFailure { error_code } => {
+                            counts.failed += 1;
+                            warn!("failed to partition {}.{:04x}.{:02x}.{:?} [{}]: {:?}",
+                                  slot, block_index, outer_index, inner_index,
+                                  bs58::encode(signature).into_string(), error_code);
+                            let serialized = bincode::serialize(&instruction)?;
+                            self.writer.write(
+                                self.partition_failures_table,
+                                &[
+                                    &program_key.as_ref(),
+                                    &slot,
+                                    &block_index,
+                                    &outer_index,
+                                    &inner_index,
+                                    &signature,
+                                    &serialized,
+                                ],
+                            )?;
+                        }
+                        Reason::NoMatchingPartitioner => counts.ignored += 1,
+                        Reason::PartitionerReturnedNone => counts.returned_none += 1,
+                    }
+                }
+            }
+            Err(err) => {
+                self.stats.failed_transactions += 1;
+                warn!("failed to partition {}.{:04x} [{}]: {:?}",
+                      slot, block_index, bs58::encode(signature).into_string(), err);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.writer.is_full()
+    }
+
+    pub fn flush_if_full(&mut self, psql_client: &mut postgres::Client) -> Result<()> {
+        self.writer.flush_if_full(psql_client)
+    }
+
+    pub fn flush(&mut self, psql_client: &mut postgres::Client) -> Result<()> {
+        self.writer.flush(psql_client)
+    }
+
+    pub fn flush_in(&mut self, db_transaction: &mut postgres::Transaction) -> Result<()> {
+        self.writer.flush_in(db_transaction)
+    }
+}
+
+// (slot, block_index) of a transaction
+pub type Position = (i64, i64);
+
+// derived rows of the transactions in [start, end). only rows for transactions that are in
+// `transactions` are touched, so partitions ingested without storing transactions stay
+const DELETE_DERIVED: [&str; 3] = [
+    "DELETE FROM partitions p
+     WHERE (p.slot, p.block_index) >= ($1, $2) AND (p.slot, p.block_index) < ($3, $4)
+       AND EXISTS (SELECT 1 FROM transactions t WHERE t.signature = p.signature)
+    ",
+    "DELETE FROM partition_failures p
+     WHERE (p.slot, p.block_index) >= ($1, $2) AND (p.slot, p.block_index) < ($3, $4)
+       AND EXISTS (SELECT 1 FROM transactions t WHERE t.signature = p.signature)
+    ",
+    "DELETE FROM account_keys a
+     USING transactions t
+     WHERE a.signature = t.signature
+       AND (t.slot, t.block_index) >= ($1, $2) AND (t.slot, t.block_index) < ($3, $4)
+    ",
+];
+
+// deletes the derived rows of the given transactions, all in slots [first_slot, last_slot], so
+// they can be partitioned again without `transactions` to go by
+pub fn delete_derived_of(
+    db_transaction: &mut postgres::Transaction,
+    first_slot: i64,
+    last_slot: i64,
+    signatures: &[Vec<u8>],
+) -> Result<()> {
+    for table in ["partitions", "partition_failures"] {
+        db_transaction.execute(
+            format!(
+                "DELETE FROM {}
+                 WHERE slot >= $1 AND slot <= $2 AND signature = ANY($3)
+                ",
+                table,
+            ).as_str(),
+            &[&first_slot, &last_slot, &signatures],
+        )?;
+    }
+    db_transaction.execute("DELETE FROM account_keys WHERE signature = ANY($1)", &[&signatures])?;
+    Ok(())
+}
+
+// those of the given transactions, all in slots [first_slot, last_slot], without any partitions.
+// that includes ones that were partitioned but had nothing to partition
+pub fn unpartitioned(
+    db_transaction: &mut postgres::Transaction,
+    first_slot: i64,
+    last_slot: i64,
+    signatures: &[Vec<u8>],
+) -> Result<HashSet<Vec<u8>>> {
+    Ok(db_transaction
+        .query(
+            "SELECT s.signature FROM unnest($3::bytea[]) AS s (signature)
+             WHERE NOT EXISTS (
+               SELECT 1 FROM partitions p
+               WHERE p.slot >= $1 AND p.slot <= $2 AND p.signature = s.signature
+             )
+            ",
+            &[&first_slot, &last_slot, &signatures],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+// replaces the derived rows of the transactions in [start, end), reading them in (slot,
+// block_index) order with its own cursor and writer. the delete and every batch are in one DB
+// transaction so a range is either fully rewritten or untouched
+fn partition_range(
+    psql_config: &str,
+    start: Position,
+    end: Position,
+    batch_size: usize,
+) -> Result<Stats> {
+    let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+
+    let select_statement = psql_client.prepare(
+        "SELECT *
+         FROM transactions
+         WHERE (slot, block_index) >= ($1, $2) AND (slot, block_index) < ($3, $4)
+         ORDER BY (slot, block_index)
+        ",
+    )?;
+
+    let mut insert_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+
+    let mut writer = PartitionWriter::new(&mut insert_client, batch_size)?;
+
+    let mut db_transaction = insert_client.transaction()?;
+    for statement in DELETE_DERIVED {
+        db_transaction.execute(statement, &[&start.0, &start.1, &end.0, &end.1])?;
+    }
+
+    let query_start = std::time::Instant::now();
+    let mut it = psql_client.query_raw(
+        &select_statement,
+        &[start.0, start.1, end.0, end.1],
+    )?;
+    debug!("query for {:?}-{:?} took {:?}", start, end, query_start.elapsed());
+
+    while let Some(row) = it.next()? {
+        let slot: i64 = row.get(0);
+        let block_index: i64 = row.get(1);
+        let signature: Vec<u8> = row.get(2);
+        let transaction: Vec<u8> = row.get(3);
+
+        let transaction = storage::decode_transaction(&transaction)?;
+
+        writer.write(slot, block_index, &signature, transaction)?;
+        if writer.is_full() {
+            writer.flush_in(&mut db_transaction)?;
+        }
+    }
+    writer.flush_in(&mut db_transaction)?;
+    db_transaction.commit()?;
+
+    Ok(writer.stats)
+}
+
+// shards per worker. smaller shards keep workers busy when transactions bunch up in a few slots
+const SHARDS_PER_WORKER: i64 = 8;
+
+// splits [start, end) into contiguous slot shards, in the order they're handed out
+fn shards(start: Position, end: Position, workers: usize) -> Vec<(Position, Position)> {
+    let shard_count = if workers <= 1 { 1 } else { workers as i64 * SHARDS_PER_WORKER };
+    let shard_size = std::cmp::max((end.0 - start.0 + shard_count) / shard_count, 1);
+    let mut bounds = vec![start];
+    let mut shard_slot = start.0 + shard_size;
+    while (shard_slot, 0) < end {
+        bounds.push((shard_slot, 0));
+        shard_slot += shard_size;
+    }
+    bounds.push(end);
+    bounds.windows(2).map(|b| (b[0], b[1])).collect()
+}
+
+// re-partitions the transactions in [start, end). `workers` threads take slot shards off a
+// shared list, each with its own cursor and writer. partitioning a transaction doesn't depend on
+// any other so the rows are the same as with one worker, just inserted in a different order.
+//
+// every shard deletes and rewrites its derived rows in its own DB transaction, so a shard is
+// either fully replaced or untouched but the range as a whole isn't. the run is recorded in
+// `partition_runs` before any shard starts and only marked finished after every shard has
+// committed. a run left unfinished (a shard failed or the process died) may have replaced only
+// some of its shards and its range needs partitioning again
+pub fn partition(
+    psql_config: &str,
+    start: Position,
+    end: Position,
+    workers: usize,
+    batch_size: usize,
+) -> Result<Stats> {
+    if start >= end {
+        return Ok(Stats::default());
+    }
+    let workers = std::cmp::max(workers, 1);
+    let run_id = start_run(psql_config, start, end)?;
+
+    let shards = shards(start, end, workers).into_iter().rev().collect::<Vec<_>>();
+    let shards = Arc::new(Mutex::new(shards));
+
+    let handles = (0..workers)
+        .map(|_| {
+            let shards = shards.clone();
+            let psql_config = psql_config.to_string();
+            std::thread::spawn(move || -> Result<Stats> {
+                let mut stats = Stats::default();
+                loop {
+                    let shard = shards.lock().unwrap().pop();
+                    let (shard_start, shard_end) = match shard {
+                        Some(shard) => shard,
+                        None => return Ok(stats),
+                    };
+                    let result = partition_range(
+                        psql_config.as_str(), shard_start, shard_end, batch_size);
+                    match result {
+                        Ok(shard_stats) => stats.merge(shard_stats),
+                        Err(err) => {
+                            // the other workers stop after their current shard
+                            shards.lock().unwrap().clear();
+                            return Err(err);
+                        }
+                    }
+                    info!("partitioned {:?}-{:?}", shard_start, shard_end);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // join everything before reporting so no worker is still writing when this returns
+    let results = handles
+        .into_iter()
+        .map(|h| h.join().map_err(|_| anyhow!("partition worker panicked"))?)
+        .collect::<Vec<_>>();
+    let mut stats = Stats::default();
+    for result in results {
+        match result {
+            Ok(worker_stats) => stats.merge(worker_stats),
+            Err(err) => {
+                warn!("partition run {} over {:?}-{:?} failed and is left unfinished. \
+                       partition the range again", run_id, start, end);
+                return Err(err);
+            }
+        }
+    }
+
+    finish_run(psql_config, run_id, &stats)?;
+    Ok(stats)
+}
+
+// records a run over [start, end) as in progress, warning about earlier runs over the range that
+// never finished
+fn start_run(psql_config: &str, start: Position, end: Position) -> Result<i64> {
+    let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+    for row in psql_client.query(
+        "SELECT id, start_slot, start_block_index, end_slot, end_block_index
+         FROM partition_runs
+         WHERE finished_at IS NULL
+           AND (start_slot, start_block_index) < ($3, $4)
+           AND (end_slot, end_block_index) > ($1, $2)
+        ",
+        &[&start.0, &start.1, &end.0, &end.1],
+    )? {
+        let (id, run_start, run_end): (i64, Position, Position) =
+            (row.get(0), (row.get(1), row.get(2)), (row.get(3), row.get(4)));
+        warn!("partition run {} over {:?}-{:?} never finished", id, run_start, run_end);
+    }
+    Ok(psql_client.query_one(
+        "INSERT INTO partition_runs
+           (started_at, start_slot, start_block_index, end_slot, end_block_index,
+            transactions, failed_transactions)
+         VALUES (now(), $1, $2, $3, $4, 0, 0)
+         RETURNING id",
+        &[&start.0, &start.1, &end.0, &end.1],
+    )?.get(0))
+}
+
+// marks the run finished with its counts in `partition_runs` and `partition_stats`
+fn finish_run(psql_config: &str, run_id: i64, stats: &Stats) -> Result<()> {
+    let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+    let mut db_transaction = psql_client.transaction()?;
+    db_transaction.execute(
+        "UPDATE partition_runs
+         SET finished_at = now(), transactions = $2, failed_transactions = $3
+         WHERE id = $1",
+        &[&run_id, &(stats.transactions as i64), &(stats.failed_transactions as i64)],
+    )?;
+
+    let insert_statement = db_transaction.prepare(
+        "INSERT INTO partition_stats VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )?;
+    for ((program_key, variant), counts) in &stats.instructions {
+        db_transaction.execute(
+            &insert_statement,
+            &[
+                &run_id,
+                &program_key.as_ref(),
+                variant,
+                &(counts.partitioned as i64),
+                &(counts.ignored as i64),
+                &(counts.returned_none as i64),
+                &(counts.failed as i64),
+            ],
+        )?;
+    }
+    db_transaction.commit()?;
+    Ok(())
+}
+
+// first transaction in `order`
+fn first_position(psql_client: &mut postgres::Client, order: &str) -> Result<Option<Position>> {
+    Ok(psql_client
+        .query_opt(
+            format!(
+                "SELECT slot, block_index FROM transactions
+                 ORDER BY slot {0}, block_index {0}
+                 LIMIT 1
+                ",
+                order,
+            ).as_str(),
+            &[],
+        )?
+        .map(|row| (row.get(0), row.get(1))))
+}
+
+// the first and last transaction, for ranges left open
+pub fn bounds(psql_client: &mut postgres::Client) -> Result<Option<(Position, Position)>> {
+    Ok(first_position(psql_client, "ASC")?.zip(first_position(psql_client, "DESC")?))
+}
+
+pub fn load_watermark(psql_client: &mut postgres::Client) -> Result<Option<Position>> {
+    Ok(psql_client
+        .query_opt("SELECT slot, block_index FROM partition_watermark", &[])?
+        .map(|row| (row.get(0), row.get(1))))
+}
+
+// partitions everything in `transactions` after the watermark and moves it to the last
+// transaction. transactions that show up behind the watermark later, e.g from fetching older
+// slots, need an explicit re-partition of their range
+pub fn partition_new(psql_config: &str, workers: usize, batch_size: usize) -> Result<Stats> {
+    let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+    let watermark = load_watermark(&mut psql_client)?;
+    let (first, last) = match bounds(&mut psql_client)? {
+        Some(bounds) => bounds,
+        None => return Ok(Stats::default()),
+    };
+
+    let start = watermark.map_or(first, |(slot, block_index)| (slot, block_index + 1));
+    let end = (last.0, last.1 + 1);
+    info!("partitioning after watermark {:?} up to {:?}", watermark, last);
+    let stats = partition(psql_config, start, end, workers, batch_size)?;
+
+    psql_client.execute(
+        "INSERT INTO partition_watermark VALUES (TRUE, $1, $2, now())
+         ON CONFLICT (id) DO UPDATE
+         SET slot = EXCLUDED.slot, block_index = EXCLUDED.block_index, updated_at = now()
+         WHERE (partition_watermark.slot, partition_watermark.block_index)
+             < (EXCLUDED.slot, EXCLUDED.block_index)
+        ",
+        &[&last.0, &last.1],
+    )?;
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shards_cover_the_range_in_order() {
+        for workers in [1, 2, 3, 7] {
+            let (start, end) = ((100, 5), (1000, 2));
+            let shards = shards(start, end, workers);
+            assert_eq!(shards.first().unwrap().0, start);
+            assert_eq!(shards.last().unwrap().1, end);
+            for pair in shards.windows(2) {
+                assert!(pair[0].0 < pair[0].1);
+                assert_eq!(pair[0].1, pair[1].0);
+            }
+        }
+    }
+
+    #[test]
+    fn one_worker_takes_the_whole_range() {
+        assert_eq!(shards((10, 0), (20, 3), 1), vec![((10, 0), (20, 3))]);
+    }
+
+    #[test]
+    fn shards_split_a_single_slot_range_once() {
+        assert_eq!(shards((10, 2), (11, 0), 4), vec![((10, 2), (11, 0))]);
+    }
+}
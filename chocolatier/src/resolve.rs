@@ -0,0 +1,435 @@
+use {
+    anyhow::{Result, anyhow},
+    log::*,
+    serde_json::Value,
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        path::PathBuf,
+    },
+};
+
+pub trait Fetcher {
+    fn fetch(&self, uri: &str) -> Result<Vec<u8>>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Gateways {
+    pub arweave: String,
+
+    pub ipfs: String,
+}
+
+impl Default for Gateways {
+    fn default() -> Self {
+        Self {
+            arweave: "https://arweave.net".to_string(),
+            ipfs: "https://ipfs.io/ipfs".to_string(),
+        }
+    }
+}
+
+impl Gateways {
+    // content-addressed uris to something we can GET. http uris that point at some other ipfs
+    // gateway are moved to ours since a lot of the old ones are dead
+    pub fn rewrite(&self, uri: &str) -> String {
+        let gateway = |base: &str, path: &str| {
+            format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/'))
+        };
+        if let Some(path) = uri.strip_prefix("ar://") {
+            gateway(&self.arweave, path)
+        } else if let Some(path) = uri.strip_prefix("ipfs://") {
+            gateway(&self.ipfs, path.strip_prefix("ipfs/").unwrap_or(path))
+        } else if let Some((_, path)) = uri.split_once("/ipfs/") {
+            gateway(&self.ipfs, path)
+        } else {
+            uri.to_string()
+        }
+    }
+}
+
+// content-addressed so the document for a given uri never changes
+pub fn is_immutable(uri: &str) -> bool {
+    uri.starts_with("ar://")
+        || uri.starts_with("ipfs://")
+        || uri.contains("/ipfs/")
+        || uri.starts_with("https://arweave.net/")
+}
+
+pub struct HttpFetcher {
+    client: reqwest::blocking::Client,
+
+    gateways: Gateways,
+}
+
+impl HttpFetcher {
+    pub fn new(gateways: Gateways, timeout: std::time::Duration) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()?,
+            gateways,
+        })
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, uri: &str) -> Result<Vec<u8>> {
+        let url = self.gateways.rewrite(uri);
+        let response = self.client.get(url.as_str()).send()?.error_for_status()?;
+        Ok(response.bytes()?.to_vec())
+    }
+}
+
+// reads documents from `root` for tests and offline use. each uri maps to a file named by the uri
+// with everything outside [A-Za-z0-9._-] replaced by '_', e.g
+// https://arweave.net/abc -> https___arweave.net_abc
+pub struct LocalFetcher {
+    root: PathBuf,
+}
+
+impl LocalFetcher {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn path_for(&self, uri: &str) -> PathBuf {
+        let name = uri
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            })
+            .collect::<String>();
+        self.root.join(name)
+    }
+}
+
+impl Fetcher for LocalFetcher {
+    fn fetch(&self, uri: &str) -> Result<Vec<u8>> {
+        let path = self.path_for(uri);
+        std::fs::read(&path).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+pub fn content_hash(content: &[u8]) -> Vec<u8> {
+    Sha256::digest(content).to_vec()
+}
+
+// checks a document against the metaplex token metadata standard. returns a description of each
+// violation rather than failing since plenty of real documents are only mostly compliant
+pub fn validate_document(document: &Value) -> Vec<String> {
+    let mut violations = vec![];
+
+    let object = match document.as_object() {
+        Some(object) => object,
+        None => return vec!["document is not an object".to_string()],
+    };
+
+    let mut expect = |path: &str, value: Option<&Value>, required: bool, ok: fn(&Value) -> bool| {
+        match value {
+            Some(v) if !ok(v) => violations.push(format!("{}: unexpected type", path)),
+            None if required => violations.push(format!("{}: missing", path)),
+            _ => {}
+        }
+    };
+
+    expect("name", object.get("name"), true, Value::is_string);
+    expect("image", object.get("image"), true, Value::is_string);
+    expect("symbol", object.get("symbol"), false, Value::is_string);
+    expect("description", object.get("description"), false, Value::is_string);
+    expect("external_url", object.get("external_url"), false, Value::is_string);
+    expect("animation_url", object.get("animation_url"), false, Value::is_string);
+    expect("seller_fee_basis_points", object.get("seller_fee_basis_points"), false, Value::is_u64);
+    expect("attributes", object.get("attributes"), false, Value::is_array);
+    expect("properties", object.get("properties"), false, Value::is_object);
+
+    for (i, attribute) in object
+        .get("attributes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let path = format!("attributes[{}]", i);
+        expect(&format!("{}.trait_type", path), attribute.get("trait_type"), true, Value::is_string);
+        expect(&format!("{}.value", path), attribute.get("value"), true,
+               |v| v.is_string() || v.is_number());
+    }
+
+    let properties = object.get("properties");
+    expect("properties.category", properties.and_then(|p| p.get("category")), false,
+           Value::is_string);
+    expect("properties.files", properties.and_then(|p| p.get("files")), false, Value::is_array);
+    expect("properties.creators", properties.and_then(|p| p.get("creators")), false,
+           Value::is_array);
+
+    for (i, file) in properties
+        .and_then(|p| p.get("files"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let path = format!("properties.files[{}]", i);
+        expect(&format!("{}.uri", path), file.get("uri"), true, Value::is_string);
+        expect(&format!("{}.type", path), file.get("type"), true, Value::is_string);
+    }
+
+    for (i, creator) in properties
+        .and_then(|p| p.get("creators"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let path = format!("properties.creators[{}]", i);
+        expect(&format!("{}.address", path), creator.get("address"), true, Value::is_string);
+        expect(&format!("{}.share", path), creator.get("share"), true, Value::is_u64);
+    }
+
+    violations
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedDocument {
+    pub content_hash: Vec<u8>,
+
+    // None if the content isn't JSON at all
+    pub document: Option<Value>,
+
+    pub violations: Vec<String>,
+}
+
+pub fn resolve(fetcher: &dyn Fetcher, uri: &str) -> Result<ResolvedDocument> {
+    let content = fetcher.fetch(uri)?;
+    let content_hash = content_hash(&content);
+    Ok(match serde_json::from_slice::<Value>(&content) {
+        Ok(document) => ResolvedDocument {
+            content_hash,
+            violations: validate_document(&document),
+            document: Some(document),
+        },
+        Err(err) => ResolvedDocument {
+            content_hash,
+            document: None,
+            violations: vec![format!("not json: {}", err)],
+        },
+    })
+}
+
+// resolves the uri of every glazing that doesn't have a document yet or whose last fetch failed.
+// documents are cached by (uri, content_hash) so a uri that changes content gets a new document
+// and glazings that share a uri share the document
+pub fn resolve_glazings(
+    psql_client: &mut postgres::Client,
+    fetcher: &dyn Fetcher,
+) -> Result<()> {
+    let unresolved = psql_client.query(
+        "SELECT g.metadata_key, g.instruction_index, g.uri
+         FROM glazings g
+         LEFT OUTER JOIN glazing_documents d ON (
+            g.metadata_key = d.metadata_key AND g.instruction_index = d.instruction_index)
+         WHERE (d.metadata_key IS NULL OR d.error IS NOT NULL) AND g.uri <> ''
+        ",
+        &[],
+    )?;
+    info!("resolving {} glazings", unresolved.len());
+
+    let select_cached_statement = psql_client.prepare(
+        "SELECT content_hash FROM metadata_documents WHERE uri = $1 LIMIT 1"
+    )?;
+
+    let insert_document_statement = psql_client.prepare(
+        "INSERT INTO metadata_documents VALUES ($1, $2, $3, $4, now())
+         ON CONFLICT DO NOTHING"
+    )?;
+
+    let insert_glazing_document_statement = psql_client.prepare(
+        "INSERT INTO glazing_documents VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (metadata_key, instruction_index) DO UPDATE
+         SET uri = EXCLUDED.uri, content_hash = EXCLUDED.content_hash, error = EXCLUDED.error"
+    )?;
+
+    // a uri is fetched at most once per run
+    let mut resolved: HashMap<String, std::result::Result<Vec<u8>, String>> = HashMap::new();
+    let (mut fetched, mut failed) = (0, 0);
+    for row in unresolved {
+        let metadata_key: String = row.get(0);
+        let instruction_index: crate::convert::InstructionIndex = row.get(1);
+        let uri: String = row.get(2);
+
+        if !resolved.contains_key(&uri) {
+            let cached = if is_immutable(&uri) {
+                psql_client.query_opt(&select_cached_statement, &[&uri])?
+                    .map(|row| row.get::<_, Vec<u8>>(0))
+            } else {
+                None
+            };
+
+            let result = match cached {
+                Some(content_hash) => Ok(content_hash),
+                None => match resolve(fetcher, &uri) {
+                    Ok(document) => {
+                        fetched += 1;
+                        if !document.violations.is_empty() {
+                            debug!("{}: {:?}", uri, document.violations);
+                        }
+                        psql_client.query(
+                            &insert_document_statement,
+                            &[
+                                &uri,
+                                &document.content_hash,
+                                &document.document,
+                                &document.violations,
+                            ],
+                        )?;
+                        Ok(document.content_hash)
+                    }
+                    Err(err) => {
+                        failed += 1;
+                        warn!("failed to resolve {}: {}", uri, err);
+                        Err(err.to_string())
+                    }
+                },
+            };
+            resolved.insert(uri.clone(), result);
+        }
+
+        let (content_hash, error) = match &resolved[&uri] {
+            Ok(content_hash) => (Some(content_hash), None),
+            Err(err) => (None, Some(err)),
+        };
+        psql_client.query(
+            &insert_glazing_document_statement,
+            &[&metadata_key, &instruction_index, &uri, &content_hash, &error],
+        )?;
+    }
+    info!("fetched {} documents, {} failed", fetched, failed);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn rewrite_content_addressed_uris() {
+        let gateways = Gateways::default();
+        assert_eq!(gateways.rewrite("ar://abc"), "https://arweave.net/abc");
+        assert_eq!(gateways.rewrite("ipfs://Qm1/0.json"), "https://ipfs.io/ipfs/Qm1/0.json");
+        assert_eq!(gateways.rewrite("ipfs://ipfs/Qm1"), "https://ipfs.io/ipfs/Qm1");
+        assert_eq!(gateways.rewrite("https://gateway.pinata.cloud/ipfs/Qm1"),
+                   "https://ipfs.io/ipfs/Qm1");
+        assert_eq!(gateways.rewrite("https://example.com/1.json"), "https://example.com/1.json");
+    }
+
+    #[test]
+    fn rewrite_to_gateways_with_trailing_slashes() {
+        let gateways = Gateways {
+            arweave: "http://localhost:1984/".to_string(),
+            ipfs: "http://localhost:8080/ipfs/".to_string(),
+        };
+        assert_eq!(gateways.rewrite("ar:///abc"), "http://localhost:1984/abc");
+        assert_eq!(gateways.rewrite("ipfs://Qm1"), "http://localhost:8080/ipfs/Qm1");
+    }
+
+    #[test]
+    fn immutable_uris() {
+        assert!(is_immutable("ar://abc"));
+        assert!(is_immutable("ipfs://Qm1"));
+        assert!(is_immutable("https://gateway.pinata.cloud/ipfs/Qm1"));
+        assert!(is_immutable("https://arweave.net/abc"));
+        assert!(!is_immutable("https://example.com/1.json"));
+        assert!(!is_immutable("https://arweave.net.example.com/abc"));
+    }
+
+    fn compliant() -> Value {
+        json!({
+            "name": "bonbon #1",
+            "symbol": "BB",
+            "image": "https://arweave.net/image",
+            "seller_fee_basis_points": 500,
+            "attributes": [
+                { "trait_type": "flavour", "value": "praline" },
+                { "trait_type": "layers", "value": 3 },
+            ],
+            "properties": {
+                "category": "image",
+                "files": [{ "uri": "https://arweave.net/image", "type": "image/png" }],
+                "creators": [{ "address": "11111111111111111111111111111111", "share": 100 }],
+            },
+        })
+    }
+
+    #[test]
+    fn validate_compliant_document() {
+        assert_eq!(validate_document(&compliant()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn validate_missing_name() {
+        let mut document = compliant();
+        document.as_object_mut().unwrap().remove("name");
+        assert_eq!(validate_document(&document), vec!["name: missing"]);
+    }
+
+    #[test]
+    fn validate_attribute_value_type() {
+        let mut document = compliant();
+        document["attributes"][1]["value"] = json!({ "nested": true });
+        assert_eq!(validate_document(&document), vec!["attributes[1].value: unexpected type"]);
+    }
+
+    #[test]
+    fn validate_not_an_object() {
+        assert_eq!(validate_document(&json!([])), vec!["document is not an object"]);
+    }
+
+    // a directory of its own per test so they can run in parallel
+    fn fetcher(test: &str, documents: &[(&str, &[u8])]) -> LocalFetcher {
+        let root = std::env::temp_dir()
+            .join(format!("chocolatier-resolve-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let fetcher = LocalFetcher::new(root);
+        for (uri, content) in documents {
+            std::fs::write(fetcher.path_for(uri), content).unwrap();
+        }
+        fetcher
+    }
+
+    #[test]
+    fn local_fetcher_path() {
+        let fetcher = LocalFetcher::new(PathBuf::from("/docs"));
+        assert_eq!(fetcher.path_for("https://arweave.net/abc"),
+                   PathBuf::from("/docs/https___arweave.net_abc"));
+    }
+
+    #[test]
+    fn resolve_json_document() {
+        let content = serde_json::to_vec(&compliant()).unwrap();
+        let fetcher = fetcher("json", &[("ar://abc", &content)]);
+        let resolved = resolve(&fetcher, "ar://abc").unwrap();
+        assert_eq!(resolved.content_hash, content_hash(&content));
+        assert_eq!(resolved.document, Some(compliant()));
+        assert!(resolved.violations.is_empty());
+    }
+
+    #[test]
+    fn resolve_non_json_content() {
+        let fetcher = fetcher("not-json", &[("ar://png", b"\x89PNG")]);
+        let resolved = resolve(&fetcher, "ar://png").unwrap();
+        assert_eq!(resolved.content_hash, content_hash(b"\x89PNG"));
+        assert_eq!(resolved.document, None);
+        assert_eq!(resolved.violations.len(), 1);
+        assert!(resolved.violations[0].starts_with("not json: "));
+    }
+
+    #[test]
+    fn resolve_missing_document_errors() {
+        let fetcher = fetcher("missing", &[]);
+        assert!(resolve(&fetcher, "ar://nothing").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use {
+    anyhow::{Result, anyhow},
+    prost::Message,
+    solana_storage_proto::convert::generated,
+    solana_transaction_status::TransactionWithStatusMeta,
+};
+
+// how `transactions.transaction` is encoded. everything since the envelope starts with a format
+// byte that can't start a protobuf message (field number 0 is invalid) so rows written before it
+// are still readable. legacy rows are told apart by their first byte being the tag for field 1,
+// the transaction, which every row we wrote has. a message without it would start with another
+// field's tag (0x12 for the meta) and be rejected as an unknown format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // bare protobuf ConfirmedTransaction. always starts with the tag for field 1
+    Legacy,
+
+    // zstd compressed protobuf ConfirmedTransaction
+    ProtobufZstd,
+}
+
+const LEGACY_TAG: u8 = 0x0a;
+
+const PROTOBUF_ZSTD_TAG: u8 = 0x01;
+
+pub const CURRENT_FORMAT: Format = Format::ProtobufZstd;
+
+const ZSTD_LEVEL: i32 = 3;
+
+impl Format {
+    pub fn tag(self) -> u8 {
+        match self {
+            Format::Legacy => LEGACY_TAG,
+            Format::ProtobufZstd => PROTOBUF_ZSTD_TAG,
+        }
+    }
+
+    pub fn of(data: &[u8]) -> Result<Self> {
+        match data.first() {
+            Some(&LEGACY_TAG) => Ok(Format::Legacy),
+            Some(&PROTOBUF_ZSTD_TAG) => Ok(Format::ProtobufZstd),
+            Some(tag) => Err(anyhow!("Unknown transaction storage format {:#04x}", tag)),
+            None => Err(anyhow!("Empty transaction")),
+        }
+    }
+}
+
+pub fn encode_transaction(transaction: TransactionWithStatusMeta) -> Result<Vec<u8>> {
+    let protobuf_tx = generated::ConfirmedTransaction::from(transaction);
+    let mut buf = Vec::with_capacity(protobuf_tx.encoded_len());
+    protobuf_tx.encode(&mut buf)?;
+
+    let mut data = vec![CURRENT_FORMAT.tag()];
+    zstd::stream::copy_encode(buf.as_slice(), &mut data, ZSTD_LEVEL)?;
+    Ok(data)
+}
+
+pub fn decode_transaction(data: &[u8]) -> Result<TransactionWithStatusMeta> {
+    let protobuf_tx = match Format::of(data)? {
+        Format::Legacy => generated::ConfirmedTransaction::decode(data)?,
+        Format::ProtobufZstd => {
+            let buf = zstd::stream::decode_all(&data[1..])?;
+            generated::ConfirmedTransaction::decode(buf.as_slice())?
+        }
+    };
+    Ok(TransactionWithStatusMeta::try_from(protobuf_tx)?)
+}
+
+// rewrites every row not in the current format. batched by (slot, block_index) so it can be
+// stopped and restarted without redoing work. empty rows have no format to read and are left as
+// they are (get_byte errors on them, and AND doesn't guarantee the length check runs first)
+pub fn migrate(psql_client: &mut postgres::Client, batch_size: i64) -> Result<()> {
+    let select_statement = psql_client.prepare(
+        "SELECT slot, block_index, transaction
+         FROM transactions
+         WHERE (slot, block_index) > ($1, $2)
+           AND CASE WHEN length(transaction) > 0 THEN get_byte(transaction, 0) <> $3 END
+         ORDER BY slot, block_index
+         LIMIT $4
+        ",
+    )?;
+
+    let update_statement = psql_client.prepare(
+        "UPDATE transactions SET transaction = $3 WHERE slot = $1 AND block_index = $2"
+    )?;
+
+    let current_tag = i32::from(CURRENT_FORMAT.tag());
+    let (mut after_slot, mut after_block_index) = (-1i64, -1i64);
+    let (mut migrated, mut bytes_before, mut bytes_after) = (0, 0, 0);
+    loop {
+        let rows = psql_client.query(
+            &select_statement,
+            &[&after_slot, &after_block_index, &current_tag, &batch_size],
+        )?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let mut db_transaction = psql_client.transaction()?;
+        for row in &rows {
+            let slot: i64 = row.get(0);
+            let block_index: i64 = row.get(1);
+            let data: Vec<u8> = row.get(2);
+
+            let encoded = encode_transaction(decode_transaction(&data)?)?;
+            bytes_before += data.len();
+            bytes_after += encoded.len();
+            db_transaction.query(&update_statement, &[&slot, &block_index, &encoded])?;
+
+            after_slot = slot;
+            after_block_index = block_index;
+        }
+        db_transaction.commit()?;
+
+        migrated += rows.len();
+        log::info!("migrated {} transactions, up to slot {}", migrated, after_slot);
+    }
+
+    log::info!("migrated {} transactions. {} bytes -> {} bytes",
+               migrated, bytes_before, bytes_after);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            message::{legacy, VersionedMessage},
+            pubkey::Pubkey,
+            signature::Signature,
+            transaction::VersionedTransaction,
+        },
+        solana_transaction_status::{TransactionStatusMeta, VersionedTransactionWithStatusMeta},
+    };
+
+    fn transaction() -> TransactionWithStatusMeta {
+        TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![Signature::new(&[1; 64])],
+                message: VersionedMessage::Legacy(legacy::Message {
+                    account_keys: vec![Pubkey::new_from_array([2; 32])],
+                    ..legacy::Message::default()
+                }),
+            },
+            meta: TransactionStatusMeta {
+                fee: 5000,
+                pre_balances: vec![10_000],
+                post_balances: vec![5_000],
+                ..TransactionStatusMeta::default()
+            },
+        })
+    }
+
+    // protobuf fills in the meta's missing lists so compare what went in
+    fn summary(transaction: &TransactionWithStatusMeta) -> (VersionedTransaction, u64, Vec<u64>) {
+        let meta = transaction.get_status_meta().unwrap();
+        (transaction.get_transaction(), meta.fee, meta.post_balances)
+    }
+
+    // how rows were written before the envelope
+    fn legacy_encode(transaction: TransactionWithStatusMeta) -> Vec<u8> {
+        generated::ConfirmedTransaction::from(transaction).encode_to_vec()
+    }
+
+    #[test]
+    fn legacy_rows_decode() {
+        let data = legacy_encode(transaction());
+        assert_eq!(data[0], LEGACY_TAG);
+        assert_eq!(Format::of(&data).unwrap(), Format::Legacy);
+        assert_eq!(summary(&decode_transaction(&data).unwrap()), summary(&transaction()));
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        let data = encode_transaction(transaction()).unwrap();
+        assert_eq!(Format::of(&data).unwrap(), Format::ProtobufZstd);
+        assert_eq!(summary(&decode_transaction(&data).unwrap()), summary(&transaction()));
+    }
+
+    #[test]
+    fn unknown_tag_errors() {
+        let mut data = encode_transaction(transaction()).unwrap();
+        data[0] = 0x7f;
+        assert!(Format::of(&data).is_err());
+        assert!(decode_transaction(&data).is_err());
+    }
+
+    #[test]
+    fn message_without_the_transaction_is_unknown() {
+        assert!(Format::of(&[0x12, 0x00]).is_err());
+    }
+
+    #[test]
+    fn empty_errors() {
+        assert!(Format::of(&[]).is_err());
+        assert!(decode_transaction(&[]).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use {
+    anyhow::{Result, anyhow},
+    bytes::{BufMut, BytesMut},
+    postgres::types::{IsNull, ToSql, Type},
+    std::io::Write,
+};
+
+// https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9.4
+const COPY_SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TableId(usize);
+
+struct TableBuffer {
+    table: String,
+
+    copy_statement: String,
+
+    types: Vec<Type>,
+
+    buf: BytesMut,
+
+    rows: usize,
+}
+
+impl TableBuffer {
+    fn new(table: &str, columns: Vec<(String, Type)>) -> Self {
+        Self {
+            table: table.to_string(),
+            copy_statement: format!(
+                "COPY {} ({}) FROM STDIN BINARY",
+                table,
+                columns.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", "),
+            ),
+            types: columns.into_iter().map(|(_, ty)| ty).collect(),
+            buf: BytesMut::new(),
+            rows: 0,
+        }
+    }
+
+    // a tuple is its field count then each field as a length (-1 for NULL) and the value
+    fn write(&mut self, row: &[&(dyn ToSql + Sync)]) -> Result<()> {
+        if row.len() != self.types.len() {
+            return Err(anyhow!("{} has {} columns, got {} values",
+                               self.table, self.types.len(), row.len()));
+        }
+
+        // a value that fails to encode leaves nothing of its row behind
+        let row_start = self.buf.len();
+        self.buf.put_i16(row.len() as i16);
+        for (value, ty) in row.iter().zip(&self.types) {
+            let length_at = self.buf.len();
+            self.buf.put_i32(0);
+            let length = match value.to_sql_checked(ty, &mut self.buf) {
+                Ok(IsNull::Yes) => -1,
+                Ok(IsNull::No) => (self.buf.len() - length_at - 4) as i32,
+                Err(e) => {
+                    self.buf.truncate(row_start);
+                    return Err(anyhow!("{}: {}", self.table, e));
+                }
+            };
+            self.buf[length_at..length_at + 4].copy_from_slice(&length.to_be_bytes());
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    // the whole COPY stream: header, the buffered tuples and the trailer
+    fn write_copy_data(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        w.write_all(COPY_SIGNATURE)?;
+        w.write_all(&0i32.to_be_bytes())?; // flags
+        w.write_all(&0i32.to_be_bytes())?; // header extension length
+        w.write_all(&self.buf)?;
+        w.write_all(&(-1i16).to_be_bytes())?; // trailer
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.buf.clear();
+        self.rows = 0;
+    }
+}
+
+// buffers rows for any number of tables as binary COPY data and writes them all in one DB
+// transaction per batch. rows are encoded with the same ToSql impls as `query` so the convert
+// types work unchanged. columns are every column of the table in order, like `INSERT INTO t
+// VALUES (...)`
+pub struct CopyWriter {
+    tables: Vec<TableBuffer>,
+
+    batch_size: usize,
+
+    rows: usize,
+}
+
+impl CopyWriter {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            tables: vec![],
+            batch_size: std::cmp::max(batch_size, 1),
+            rows: 0,
+        }
+    }
+
+    pub fn add_table(&mut self, psql_client: &mut postgres::Client, table: &str) -> Result<TableId> {
+        // custom types (enums, composites) are only resolved through a prepared statement
+        let columns = psql_client
+            .prepare(&format!("SELECT * FROM {} LIMIT 0", table))?
+            .columns()
+            .iter()
+            .map(|c| (c.name().to_string(), c.type_().clone()))
+            .collect::<Vec<_>>();
+        self.tables.push(TableBuffer::new(table, columns));
+        Ok(TableId(self.tables.len() - 1))
+    }
+
+    pub fn write(&mut self, table: TableId, row: &[&(dyn ToSql + Sync)]) -> Result<()> {
+        self.tables[table.0].write(row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.rows >= self.batch_size
+    }
+
+    pub fn flush_if_full(&mut self, psql_client: &mut postgres::Client) -> Result<()> {
+        if self.is_full() {
+            self.flush(psql_client)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self, psql_client: &mut postgres::Client) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut db_transaction = psql_client.transaction()?;
+        self.flush_in(&mut db_transaction)?;
+        db_transaction.commit()?;
+        Ok(())
+    }
+
+    // copies everything buffered into an open transaction, for callers that need to write
+    // something else in the same commit. if it fails everything buffered is dropped, including
+    // tables that were already copied: the transaction is aborted so those rows are gone too
+    pub fn flush_in(&mut self, db_transaction: &mut postgres::Transaction) -> Result<()> {
+        let flush_start = std::time::Instant::now();
+        let result = (|| -> Result<()> {
+            for table in self.tables.iter_mut().filter(|t| t.rows > 0) {
+                let mut copy_in = db_transaction.copy_in(table.copy_statement.as_str())?;
+                table.write_copy_data(&mut copy_in)?;
+                copy_in.finish()?;
+                table.clear();
+            }
+            Ok(())
+        })();
+        if result.is_err() {
+            self.tables.iter_mut().for_each(TableBuffer::clear);
+        } else {
+            log::debug!("copied {} rows in {:?}", self.rows, flush_start.elapsed());
+        }
+        self.rows = 0;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> TableBuffer {
+        TableBuffer::new(
+            "t", vec![("a".to_string(), Type::INT8), ("b".to_string(), Type::BYTEA)])
+    }
+
+    #[test]
+    fn copy_statement_lists_the_columns() {
+        assert_eq!(table().copy_statement, "COPY t (a, b) FROM STDIN BINARY");
+    }
+
+    #[test]
+    fn copy_data_for_a_null_and_a_value() {
+        let mut table = table();
+        table.write(&[&None::<i64>, &vec![1u8, 2]]).unwrap();
+        let mut data = vec![];
+        table.write_copy_data(&mut data).unwrap();
+
+        let mut expected = b"PGCOPY\n\xff\r\n\0".to_vec();
+        expected.extend([0, 0, 0, 0]); // flags
+        expected.extend([0, 0, 0, 0]); // header extension
+        expected.extend([0, 2]); // fields
+        expected.extend([0xff, 0xff, 0xff, 0xff]); // NULL
+        expected.extend([0, 0, 0, 2, 1, 2]); // 2 bytes
+        expected.extend([0xff, 0xff]); // trailer
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn wrong_column_count_errors() {
+        let mut table = table();
+        assert!(table.write(&[&1i64]).is_err());
+        assert_eq!(table.rows, 0);
+    }
+
+    #[test]
+    fn mismatched_type_errors_without_a_partial_row() {
+        let mut table = table();
+        table.write(&[&1i64, &vec![1u8]]).unwrap();
+        let written = table.buf.len();
+        assert!(table.write(&[&1i64, &"not bytes"]).is_err());
+        assert_eq!((table.buf.len(), table.rows), (written, 1));
+    }
+}
@@ -0,0 +1,25 @@
+// end-to-end fetch against a local bigtable emulator through scripts/bigtable_emulator_test.sh.
+// needs gcloud (with the emulator component), cbt, psql and a scratch database, so it's ignored
+// by default. run it with
+//
+//   EMULATOR_BLOCKS_DIR=./blocks EMULATOR_BLOCK_RANGE=150000000-150000100 \
+//   EMULATOR_PSQL_CONFIG="host=localhost user=postgres dbname=scratch" \
+//   cargo test -p chocolatier --test bigtable_emulator -- --ignored
+
+#[test]
+#[ignore]
+fn fetch_from_bigtable_emulator() {
+    let var = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("{} isn't set", name));
+    let workspace = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+
+    let status = std::process::Command::new(workspace.join("scripts/bigtable_emulator_test.sh"))
+        .args([
+            var("EMULATOR_BLOCKS_DIR"),
+            var("EMULATOR_BLOCK_RANGE"),
+            var("EMULATOR_PSQL_CONFIG"),
+        ])
+        .current_dir(&workspace)
+        .status()
+        .expect("failed to run scripts/bigtable_emulator_test.sh");
+    assert!(status.success(), "emulator fetch doesn't match the expected transactions");
+}
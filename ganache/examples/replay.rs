@@ -0,0 +1,202 @@
+// drives the plugin through the geyser interface with recorded blocks (see `fetch --source
+// json|protobuf`) the way a validator would: every block's transactions, then processed, with
+// confirmed and rooted trailing behind. with --fork_every some blocks are also replayed on a
+// sibling slot that never confirms, and afterwards the DB is checked for exactly the blocks'
+// transactions and nothing from the forks
+use {
+    anyhow::{Result, anyhow},
+    chocolatier::{filter, ledger::{self, LedgerSource}},
+    ganache::{slots, Config, Ganache},
+    solana_geyser_plugin_interface::geyser_plugin_interface::{
+        GeyserPlugin, ReplicaTransactionInfoV2, ReplicaTransactionInfoVersions, SlotStatus,
+    },
+    solana_sdk::{
+        clock::Slot,
+        message::SimpleAddressLoader,
+        transaction::{MessageHash, SanitizedTransaction},
+    },
+    solana_transaction_status::{ConfirmedBlock, TransactionWithStatusMeta},
+    std::collections::BTreeSet,
+};
+
+fn main() -> Result<()> {
+    let matches = clap::Command::new("replay")
+        .arg(
+            clap::Arg::new("config")
+                .long("config")
+                .value_name("FILEPATH")
+                .takes_value(true)
+                .required(true)
+                .help("Plugin config, as passed to the validator")
+        )
+        .arg(
+            clap::Arg::new("blocks_dir")
+                .long("blocks_dir")
+                .value_name("DIRPATH")
+                .takes_value(true)
+                .required(true)
+                .help("Directory of per-slot block dumps")
+        )
+        .arg(
+            clap::Arg::new("format")
+                .long("format")
+                .value_name("json|protobuf")
+                .takes_value(true)
+                .default_value("json")
+                .help("Format of the block dumps")
+        )
+        .arg(
+            clap::Arg::new("confirm_lag")
+                .long("confirm_lag")
+                .value_name("BLOCKS")
+                .takes_value(true)
+                .default_value("1")
+                .help("Blocks between a slot being processed and confirmed")
+        )
+        .arg(
+            clap::Arg::new("root_lag")
+                .long("root_lag")
+                .value_name("BLOCKS")
+                .takes_value(true)
+                .default_value("32")
+                .help("Blocks between a slot being processed and rooted")
+        )
+        .arg(
+            clap::Arg::new("fork_every")
+                .long("fork_every")
+                .value_name("BLOCKS")
+                .takes_value(true)
+                .help("Also replay every Nth block on an abandoned fork")
+        )
+        .get_matches();
+
+    let config_path = matches.value_of("config").unwrap();
+    let format = match matches.value_of("format").unwrap() {
+        "json" => ledger::DumpFormat::Json,
+        "protobuf" => ledger::DumpFormat::Protobuf,
+        other => return Err(anyhow!("Unknown --format {}", other)),
+    };
+    let confirm_lag = matches.value_of("confirm_lag").unwrap().parse::<usize>()?;
+    let root_lag = std::cmp::max(
+        matches.value_of("root_lag").unwrap().parse::<usize>()?, confirm_lag);
+    let fork_every = matches.value_of("fork_every").map(|n| n.parse::<usize>()).transpose()?;
+
+    let source = ledger::DumpSource::new(
+        std::path::Path::new(matches.value_of("blocks_dir").unwrap()), format)?;
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let slots = rt.block_on(source.get_confirmed_blocks(0, usize::MAX))?;
+    let blocks = rt.block_on(source.get_blocks(&slots))?;
+    let dumped = slots.iter().cloned().collect::<BTreeSet<_>>();
+
+    let config = Config::from_file(config_path)?;
+    let filter = filter::Filter::new(&config.filter)?;
+
+    let mut plugin = Ganache::default();
+    plugin.on_load(config_path)?;
+
+    let mut expected = 0;
+    let mut forks = vec![];
+    for (index, (slot, block)) in blocks.iter().enumerate() {
+        // an empty slot just before the block, off the same parent
+        let fork_slot = slot.checked_sub(1)
+            .filter(|s| *s > block.parent_slot && !dumped.contains(s));
+        if let (Some(fork_every), Some(fork_slot)) = (fork_every, fork_slot) {
+            if fork_every != 0 && index % fork_every == 0 {
+                notify_block(&mut plugin, fork_slot, block)?;
+                plugin.update_slot_status(
+                    fork_slot, Some(block.parent_slot), SlotStatus::Processed)?;
+                forks.push(fork_slot);
+            }
+        }
+
+        expected += notify_block(&mut plugin, *slot, block)?
+            .iter()
+            .filter(|t| filter.keep(*slot, t))
+            .count();
+        plugin.update_slot_status(*slot, Some(block.parent_slot), SlotStatus::Processed)?;
+
+        if index >= confirm_lag {
+            plugin.update_slot_status(slots[index - confirm_lag], None, SlotStatus::Confirmed)?;
+        }
+        if index >= root_lag {
+            plugin.update_slot_status(slots[index - root_lag], None, SlotStatus::Rooted)?;
+        }
+    }
+    let tail = |lag: usize| slots.len().saturating_sub(lag)..slots.len();
+    for index in tail(confirm_lag) {
+        plugin.update_slot_status(slots[index], None, SlotStatus::Confirmed)?;
+    }
+    for index in tail(root_lag) {
+        plugin.update_slot_status(slots[index], None, SlotStatus::Rooted)?;
+    }
+    plugin.on_unload();
+
+    check(&config, &slots, &forks, expected)
+}
+
+// notifies the transactions in block order and returns the ones that aren't votes, converted
+// back the way the plugin does
+fn notify_block(
+    plugin: &mut Ganache,
+    slot: Slot,
+    block: &ConfirmedBlock,
+) -> Result<Vec<TransactionWithStatusMeta>> {
+    let mut notified = vec![];
+    for (index, transaction) in block.transactions.iter().enumerate() {
+        let transaction = match transaction {
+            TransactionWithStatusMeta::Complete(transaction) => transaction,
+            TransactionWithStatusMeta::MissingMetadata(_) => continue,
+        };
+        let sanitized = SanitizedTransaction::try_create(
+            transaction.transaction.clone(),
+            MessageHash::Compute,
+            None,
+            SimpleAddressLoader::Enabled(transaction.meta.loaded_addresses.clone()),
+        )?;
+        let is_vote = sanitized.is_simple_vote_transaction();
+        plugin.notify_transaction(
+            ReplicaTransactionInfoVersions::V0_0_2(&ReplicaTransactionInfoV2 {
+                signature: sanitized.signature(),
+                is_vote,
+                transaction: &sanitized,
+                transaction_status_meta: &transaction.meta,
+                index,
+            }),
+            slot,
+        )?;
+        if !is_vote {
+            notified.push(TransactionWithStatusMeta::Complete(transaction.clone()));
+        }
+    }
+    Ok(notified)
+}
+
+fn check(config: &Config, slots: &[Slot], forks: &[Slot], expected: usize) -> Result<()> {
+    let (first_slot, last_slot) = match (slots.first(), slots.last()) {
+        (Some(first_slot), Some(last_slot)) => (*first_slot as i64, *last_slot as i64),
+        _ => return Ok(()),
+    };
+
+    let mut psql_client = postgres::Client::connect(
+        config.psql_config.as_str(), postgres::NoTls)?;
+    let written: i64 = psql_client.query_one(
+        "SELECT count(*) FROM transactions WHERE slot = ANY($1)",
+        &[&slots.iter().map(|s| *s as i64).collect::<Vec<_>>()],
+    )?.get(0);
+    let forked: i64 = psql_client.query_one(
+        "SELECT count(*) FROM transactions WHERE slot = ANY($1)",
+        &[&forks.iter().map(|s| *s as i64).collect::<Vec<_>>()],
+    )?.get(0);
+
+    println!("slots {}-{}: {} transactions expected, {} in the DB, {} from {} forks",
+             first_slot, last_slot, expected, written, forked, forks.len());
+    // processed writes the forks, and their copies of the transactions win
+    let ok = match config.commitment {
+        slots::Commitment::Processed => (written + forked) as usize == expected,
+        _ => written as usize == expected && forked == 0,
+    };
+    if !ok {
+        return Err(anyhow!("DB doesn't match the replayed blocks"));
+    }
+    Ok(())
+}
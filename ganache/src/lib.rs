@@ -0,0 +1,256 @@
+// geyser plugin that writes transactions, partitions and account keys as the validator replays
+// blocks, with the same schema as chocolatier fetch + partition. load it with a config like
+//
+//   {
+//     "libpath": "target/release/libganache.so",
+//     "psql_config": "host=localhost user=postgres",
+//     "commitment": "confirmed",
+//     "filter": { "programs": ["TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"] }
+//   }
+//
+// transactions are written at their position in the block, which only comes with v0.0.2 transaction
+// notifications, so older validators are rejected. see examples/replay.rs for driving it from
+// recorded blocks
+
+pub mod slots;
+pub mod writer;
+
+use {
+    anyhow::{Result, anyhow},
+    chocolatier::filter,
+    crossbeam_channel::{Receiver, Sender},
+    log::*,
+    serde::Deserialize,
+    solana_geyser_plugin_interface::geyser_plugin_interface::{
+        GeyserPlugin, GeyserPluginError, ReplicaAccountInfoVersions,
+        ReplicaTransactionInfoVersions, Result as PluginResult, SlotStatus,
+    },
+    solana_sdk::clock::Slot,
+    solana_transaction_status::{
+        TransactionWithStatusMeta, VersionedTransactionWithStatusMeta,
+    },
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub psql_config: String,
+
+    #[serde(default)]
+    pub commitment: slots::Commitment,
+
+    // rows per COPY. slots are always written whole
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    // notifications queued for the writer. the validator waits when it's full
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+
+    // same as fetch --filter_config
+    #[serde(default)]
+    pub filter: filter::FilterSpec,
+}
+
+fn default_batch_size() -> usize {
+    10000
+}
+
+fn default_queue_size() -> usize {
+    100000
+}
+
+impl Config {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| anyhow!("{}: {}", path, e))
+    }
+}
+
+enum Event {
+    Transaction {
+        slot: Slot,
+
+        pending: slots::PendingTransaction,
+    },
+
+    SlotStatus {
+        slot: Slot,
+
+        parent: Option<Slot>,
+
+        status: SlotStatus,
+    },
+}
+
+#[derive(Debug)]
+struct Running {
+    filter: filter::Filter,
+
+    sender: Sender<Event>,
+
+    handle: std::thread::JoinHandle<Result<()>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Ganache {
+    running: Option<Running>,
+}
+
+fn write_slots(
+    mut slot_writer: writer::SlotWriter,
+    commitment: slots::Commitment,
+    receiver: Receiver<Event>,
+) -> Result<()> {
+    let mut tracker = slots::SlotTracker::new(commitment);
+    for event in receiver {
+        match event {
+            Event::Transaction { slot, pending } => {
+                if let Some(pending) = tracker.add_transaction(slot, pending) {
+                    slot_writer.write(slot, vec![pending])?;
+                }
+            }
+            Event::SlotStatus { slot, parent, status } => {
+                let slots::Update { ready, dropped } =
+                    tracker.update_status(slot, parent, status);
+                for (slot, transactions) in ready {
+                    slot_writer.write(slot, transactions)?;
+                }
+                for (slot, count) in dropped {
+                    info!("dropped {} transactions from abandoned slot {}", count, slot);
+                }
+            }
+        }
+    }
+
+    if tracker.pending() != 0 {
+        warn!("{} slots never reached {:?}", tracker.pending(), commitment);
+    }
+    info!("wrote {} new transactions", slot_writer.written);
+    Ok(())
+}
+
+impl Ganache {
+    fn send(&self, event: Event) -> Result<()> {
+        let running = self.running.as_ref().ok_or(anyhow!("plugin not loaded"))?;
+        running.sender.send(event).map_err(|_| anyhow!("writer stopped"))
+    }
+}
+
+fn plugin_error(err: anyhow::Error) -> GeyserPluginError {
+    GeyserPluginError::Custom(err.into())
+}
+
+impl GeyserPlugin for Ganache {
+    fn name(&self) -> &'static str {
+        "ganache"
+    }
+
+    fn on_load(&mut self, config_file: &str) -> PluginResult<()> {
+        solana_logger::setup_with_default("info");
+
+        let config = Config::from_file(config_file).map_err(|e|
+            GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+        let filter = filter::Filter::new(&config.filter).map_err(plugin_error)?;
+
+        // connect here so a bad config fails the load instead of the first write
+        let slot_writer = writer::SlotWriter::new(config.psql_config.as_str(), config.batch_size)
+            .map_err(plugin_error)?;
+        let (sender, receiver) = crossbeam_channel::bounded(std::cmp::max(config.queue_size, 1));
+        let commitment = config.commitment;
+        let handle = std::thread::spawn(move || write_slots(slot_writer, commitment, receiver));
+
+        info!("loaded with commitment {:?} and filter {}",
+              config.commitment, config.filter.to_json().map_err(plugin_error)?);
+        self.running = Some(Running { filter, sender, handle });
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        if let Some(Running { sender, handle, .. }) = self.running.take() {
+            drop(sender);
+            match handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => error!("writer failed: {}", err),
+                Err(_) => error!("writer panicked"),
+            }
+        }
+    }
+
+    fn update_account(
+        &mut self,
+        _account: ReplicaAccountInfoVersions,
+        _slot: Slot,
+        _is_startup: bool,
+    ) -> PluginResult<()> {
+        Ok(())
+    }
+
+    fn notify_end_of_startup(&mut self) -> PluginResult<()> {
+        Ok(())
+    }
+
+    fn update_slot_status(
+        &mut self,
+        slot: Slot,
+        parent: Option<Slot>,
+        status: SlotStatus,
+    ) -> PluginResult<()> {
+        self.send(Event::SlotStatus { slot, parent, status }).map_err(plugin_error)
+    }
+
+    fn notify_transaction(
+        &mut self,
+        transaction: ReplicaTransactionInfoVersions,
+        slot: Slot,
+    ) -> PluginResult<()> {
+        // the position in the block only comes with v2. notification order isn't it: entries are
+        // replayed in parallel batches
+        let info = match transaction {
+            ReplicaTransactionInfoVersions::V0_0_2(info) => info,
+            ReplicaTransactionInfoVersions::V0_0_1(_) => {
+                return Err(GeyserPluginError::TransactionUpdateError {
+                    msg: "transaction notification without its index in the block".to_string(),
+                });
+            }
+        };
+        if info.is_vote {
+            return Ok(());
+        }
+
+        let transaction = TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction: info.transaction.to_versioned_transaction(),
+            meta: info.transaction_status_meta.clone(),
+        });
+        let keep = self.running.as_ref().map_or(false, |r| r.filter.keep(slot, &transaction));
+        if !keep {
+            return Ok(());
+        }
+
+        self.send(Event::Transaction {
+            slot,
+            pending: slots::PendingTransaction {
+                block_index: info.index as i64,
+                signature: info.signature.as_ref().to_vec(),
+                transaction,
+            },
+        }).map_err(plugin_error)
+    }
+
+    fn account_data_notifications_enabled(&self) -> bool {
+        false
+    }
+
+    fn transaction_notifications_enabled(&self) -> bool {
+        true
+    }
+}
+
+/// # Safety
+///
+/// called by the validator's plugin manager, which takes ownership of the returned plugin
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
+    let plugin: Box<dyn GeyserPlugin> = Box::new(Ganache::default());
+    Box::into_raw(plugin)
+}
@@ -0,0 +1,267 @@
+use {
+    serde::Deserialize,
+    solana_geyser_plugin_interface::geyser_plugin_interface::SlotStatus,
+    solana_sdk::clock::Slot,
+    solana_transaction_status::TransactionWithStatusMeta,
+    std::collections::{BTreeMap, HashSet},
+};
+
+// how final a slot has to be before its transactions are written. processed slots can still be
+// on a fork that gets abandoned, and those transactions stay in the DB
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Rooted,
+}
+
+impl Default for Commitment {
+    fn default() -> Self {
+        Commitment::Confirmed
+    }
+}
+
+impl Commitment {
+    fn rank(&self) -> u8 {
+        match self {
+            Commitment::Processed => 0,
+            Commitment::Confirmed => 1,
+            Commitment::Rooted => 2,
+        }
+    }
+
+    pub fn reached_by(&self, status: &SlotStatus) -> bool {
+        let status = match status {
+            SlotStatus::Processed => Commitment::Processed,
+            SlotStatus::Confirmed => Commitment::Confirmed,
+            SlotStatus::Rooted => Commitment::Rooted,
+        };
+        status.rank() >= self.rank()
+    }
+}
+
+pub struct PendingTransaction {
+    // position in the block, the same as fetch writes
+    pub block_index: i64,
+
+    pub signature: Vec<u8>,
+
+    pub transaction: TransactionWithStatusMeta,
+}
+
+#[derive(Default)]
+struct SlotState {
+    parent: Option<Slot>,
+
+    // reached the commitment. later transactions for the slot are written as they come
+    committed: bool,
+
+    transactions: Vec<PendingTransaction>,
+}
+
+// holds transactions per slot until the slot reaches the commitment. when a slot is rooted
+// everything below it is settled: its ancestors are final even if their own notifications were
+// missed and any other slot still pending was on a dead fork
+pub struct SlotTracker {
+    commitment: Commitment,
+
+    slots: BTreeMap<Slot, SlotState>,
+
+    root: Option<Slot>,
+}
+
+pub struct Update {
+    // slots to write, in slot order
+    pub ready: Vec<(Slot, Vec<PendingTransaction>)>,
+
+    // abandoned slots and how many transactions they had
+    pub dropped: Vec<(Slot, usize)>,
+}
+
+impl SlotTracker {
+    pub fn new(commitment: Commitment) -> Self {
+        Self {
+            commitment,
+            slots: BTreeMap::new(),
+            root: None,
+        }
+    }
+
+    // returns the transaction back if its slot is already committed
+    pub fn add_transaction(
+        &mut self,
+        slot: Slot,
+        pending: PendingTransaction,
+    ) -> Option<PendingTransaction> {
+        let past_root = self.root.map_or(false, |root| slot <= root);
+        let state = self.slots.entry(slot).or_default();
+        if state.committed || past_root {
+            Some(pending)
+        } else {
+            state.transactions.push(pending);
+            None
+        }
+    }
+
+    pub fn update_status(
+        &mut self,
+        slot: Slot,
+        parent: Option<Slot>,
+        status: SlotStatus,
+    ) -> Update {
+        let mut update = Update { ready: vec![], dropped: vec![] };
+
+        let state = self.slots.entry(slot).or_default();
+        if parent.is_some() {
+            state.parent = parent;
+        }
+        if self.commitment.reached_by(&status) {
+            Self::commit(slot, state, &mut update);
+        }
+
+        if let SlotStatus::Rooted = status {
+            let mut ancestors = HashSet::new();
+            let mut next = self.slots.get(&slot).and_then(|s| s.parent);
+            while let Some(ancestor) = next {
+                ancestors.insert(ancestor);
+                next = self.slots.get(&ancestor).and_then(|s| s.parent);
+            }
+
+            let settled = self.slots.range(..slot).map(|(s, _)| *s).collect::<Vec<_>>();
+            for settled_slot in settled {
+                let mut state = self.slots.remove(&settled_slot).unwrap();
+                if ancestors.contains(&settled_slot) {
+                    Self::commit(settled_slot, &mut state, &mut update);
+                } else if !state.transactions.is_empty() {
+                    update.dropped.push((settled_slot, state.transactions.len()));
+                }
+            }
+            update.ready.sort_by_key(|(s, _)| *s);
+
+            self.slots.remove(&slot);
+            self.root = Some(std::cmp::max(slot, self.root.unwrap_or(0)));
+        }
+
+        update
+    }
+
+    fn commit(slot: Slot, state: &mut SlotState, update: &mut Update) {
+        state.committed = true;
+        if !state.transactions.is_empty() {
+            update.ready.push((slot, std::mem::take(&mut state.transactions)));
+        }
+    }
+
+    // slots still waiting on the commitment
+    pub fn pending(&self) -> usize {
+        self.slots.values().filter(|s| !s.transactions.is_empty()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::transaction::Transaction};
+
+    fn pending(block_index: i64) -> PendingTransaction {
+        PendingTransaction {
+            block_index,
+            signature: vec![block_index as u8; 64],
+            transaction: TransactionWithStatusMeta::MissingMetadata(Transaction::default()),
+        }
+    }
+
+    fn ready(update: &Update) -> Vec<(Slot, Vec<i64>)> {
+        update.ready
+            .iter()
+            .map(|(slot, transactions)| (*slot, transactions.iter().map(|t| t.block_index).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn commitment_reached_by() {
+        let statuses = [SlotStatus::Processed, SlotStatus::Confirmed, SlotStatus::Rooted];
+        let reached = |commitment: Commitment| statuses
+            .iter()
+            .map(|status| commitment.reached_by(status))
+            .collect::<Vec<_>>();
+        assert_eq!(reached(Commitment::Processed), vec![true, true, true]);
+        assert_eq!(reached(Commitment::Confirmed), vec![false, true, true]);
+        assert_eq!(reached(Commitment::Rooted), vec![false, false, true]);
+    }
+
+    #[test]
+    fn processed_writes_when_processed() {
+        let mut tracker = SlotTracker::new(Commitment::Processed);
+        assert!(tracker.add_transaction(1, pending(0)).is_none());
+        let update = tracker.update_status(1, Some(0), SlotStatus::Processed);
+        assert_eq!(ready(&update), vec![(1, vec![0])]);
+        assert_eq!(tracker.pending(), 0);
+    }
+
+    #[test]
+    fn confirmed_holds_until_confirmed() {
+        let mut tracker = SlotTracker::new(Commitment::Confirmed);
+        assert!(tracker.add_transaction(1, pending(1)).is_none());
+        assert!(tracker.add_transaction(1, pending(0)).is_none());
+
+        let update = tracker.update_status(1, Some(0), SlotStatus::Processed);
+        assert_eq!(ready(&update), vec![]);
+        assert_eq!(tracker.pending(), 1);
+
+        let update = tracker.update_status(1, None, SlotStatus::Confirmed);
+        assert_eq!(ready(&update), vec![(1, vec![1, 0])]);
+        assert_eq!(tracker.pending(), 0);
+
+        // already committed, so written as it comes
+        assert_eq!(tracker.add_transaction(1, pending(2)).map(|t| t.block_index), Some(2));
+    }
+
+    #[test]
+    fn rooted_holds_through_confirmed() {
+        let mut tracker = SlotTracker::new(Commitment::Rooted);
+        tracker.add_transaction(1, pending(0));
+        tracker.update_status(1, Some(0), SlotStatus::Processed);
+        let update = tracker.update_status(1, None, SlotStatus::Confirmed);
+        assert_eq!(ready(&update), vec![]);
+
+        let update = tracker.update_status(1, None, SlotStatus::Rooted);
+        assert_eq!(ready(&update), vec![(1, vec![0])]);
+    }
+
+    #[test]
+    fn root_commits_ancestors_and_drops_forks() {
+        let mut tracker = SlotTracker::new(Commitment::Rooted);
+        // 1 <- 2 <- 4 with 3 forked off 1
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 2)] {
+            tracker.add_transaction(slot, pending(0));
+            tracker.update_status(slot, Some(parent), SlotStatus::Processed);
+        }
+        assert_eq!(tracker.pending(), 4);
+
+        let update = tracker.update_status(4, None, SlotStatus::Rooted);
+        assert_eq!(ready(&update), vec![(1, vec![0]), (2, vec![0]), (4, vec![0])]);
+        assert_eq!(update.dropped, vec![(3, 1)]);
+        assert_eq!(tracker.pending(), 0);
+
+        // anything at or below the root is final
+        assert!(tracker.add_transaction(2, pending(1)).is_some());
+        assert!(tracker.add_transaction(5, pending(0)).is_none());
+    }
+
+    #[test]
+    fn root_commits_ancestors_that_missed_confirmed() {
+        let mut tracker = SlotTracker::new(Commitment::Confirmed);
+        tracker.add_transaction(1, pending(0));
+        tracker.update_status(1, Some(0), SlotStatus::Processed);
+        tracker.add_transaction(2, pending(0));
+        tracker.update_status(2, Some(1), SlotStatus::Processed);
+
+        let update = tracker.update_status(2, None, SlotStatus::Confirmed);
+        assert_eq!(ready(&update), vec![(2, vec![0])]);
+
+        let update = tracker.update_status(2, None, SlotStatus::Rooted);
+        assert_eq!(ready(&update), vec![(1, vec![0])]);
+        assert!(update.dropped.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use {
+    crate::slots::PendingTransaction,
+    anyhow::Result,
+    chocolatier::{partition, storage, writer},
+    log::*,
+    solana_sdk::clock::Slot,
+    std::collections::HashSet,
+};
+
+// writes a slot's transactions and their partitions in one DB transaction. transactions go
+// through a staging table like fetch so a slot that was already fetched (or written before a
+// restart) is skipped, and only transactions that were actually inserted get partitioned
+pub struct SlotWriter {
+    psql_client: postgres::Client,
+
+    writer: writer::CopyWriter,
+
+    staging_table: writer::TableId,
+
+    partition_writer: partition::PartitionWriter,
+
+    pub written: u64,
+}
+
+impl SlotWriter {
+    pub fn new(psql_config: &str, batch_size: usize) -> Result<Self> {
+        let mut psql_client = postgres::Client::connect(psql_config, postgres::NoTls)?;
+        psql_client.batch_execute(
+            "CREATE TEMP TABLE transactions_staging (LIKE transactions) ON COMMIT DELETE ROWS"
+        )?;
+        let mut writer = writer::CopyWriter::new(batch_size);
+        let staging_table = writer.add_table(&mut psql_client, "transactions_staging")?;
+        let partition_writer = partition::PartitionWriter::new(&mut psql_client, batch_size)?;
+        Ok(Self {
+            psql_client,
+            writer,
+            staging_table,
+            partition_writer,
+            written: 0,
+        })
+    }
+
+    pub fn write(&mut self, slot: Slot, transactions: Vec<PendingTransaction>) -> Result<()> {
+        let slot = slot as i64;
+        for pending in &transactions {
+            self.writer.write(
+                self.staging_table,
+                &[
+                    &slot,
+                    &pending.block_index,
+                    &pending.signature,
+                    &storage::encode_transaction(pending.transaction.clone())?,
+                ],
+            )?;
+        }
+
+        let mut db_transaction = self.psql_client.transaction()?;
+        self.writer.flush_in(&mut db_transaction)?;
+        let inserted = db_transaction
+            .query(
+                "INSERT INTO transactions SELECT * FROM transactions_staging
+                 ON CONFLICT (signature) DO NOTHING
+                 RETURNING signature",
+                &[],
+            )?
+            .into_iter()
+            .map(|row| row.get::<_, Vec<u8>>(0))
+            .collect::<HashSet<_>>();
+
+        for pending in transactions {
+            if inserted.contains(&pending.signature) {
+                self.partition_writer.write(
+                    slot, pending.block_index, &pending.signature, pending.transaction)?;
+            }
+        }
+        self.partition_writer.flush_in(&mut db_transaction)?;
+        db_transaction.commit()?;
+
+        debug!("wrote {} new transactions for slot {}", inserted.len(), slot);
+        self.written += inserted.len() as u64;
+        Ok(())
+    }
+}
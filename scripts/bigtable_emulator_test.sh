@@ -0,0 +1,84 @@
+#!/usr/bin/env bash
+# End-to-end fetch against a local bigtable emulator. Loads recorded blocks into the emulator,
+# runs `chocolatier fetch` into a scratch database and compares the resulting transactions rows
+# with the expected file next to the blocks.
+#
+#   scripts/bigtable_emulator_test.sh BLOCKS_DIR BLOCK_RANGE PSQL_CONFIG [--record]
+#
+# BLOCKS_DIR holds per-slot getBlock dumps (e.g 150000000.json) and expected_transactions.txt,
+# one "slot block_index signature_hex" line per row ordered by slot and block_index. --record
+# rewrites expected_transactions.txt from this run instead of comparing against it.
+#
+# Needs gcloud (with the bigtable emulator component), cbt and psql on PATH. PSQL_CONFIG is the
+# same connection string as --psql_config and the database is dropped and recreated.
+# `cargo test -p chocolatier --test bigtable_emulator -- --ignored` runs this too.
+
+set -euo pipefail
+
+if [[ $# -lt 3 ]]; then
+  sed -n '2,14p' "$0"
+  exit 1
+fi
+
+blocks_dir=$1
+block_range=$2
+psql_config=$3
+record=${4:-}
+
+here=$(cd "$(dirname "$0")" && pwd)
+emulator_host=${BIGTABLE_EMULATOR_HOST:-localhost:8086}
+instance=solana-ledger
+workdir=$(mktemp -d)
+
+cleanup() {
+  [[ -n ${emulator_pid:-} ]] && kill "$emulator_pid" 2>/dev/null || true
+  rm -rf "$workdir"
+}
+trap cleanup EXIT
+
+gcloud beta emulators bigtable start --host-port="$emulator_host" >"$workdir/emulator.log" 2>&1 &
+emulator_pid=$!
+for _ in $(seq 1 30); do
+  if BIGTABLE_EMULATOR_HOST=$emulator_host cbt -project emulator -instance $instance ls \
+      >/dev/null 2>&1; then
+    break
+  fi
+  sleep 1
+done
+
+# the tables solana-ledger-tool bigtable upload expects
+for table in blocks tx tx-by-addr; do
+  BIGTABLE_EMULATOR_HOST=$emulator_host cbt -project emulator -instance $instance createtable $table
+  BIGTABLE_EMULATOR_HOST=$emulator_host cbt -project emulator -instance $instance createfamily $table x
+done
+
+cargo run --release --example load_bigtable -- \
+  --blocks_dir "$blocks_dir" \
+  --emulator_host "$emulator_host" \
+  --instance $instance
+
+psql "$psql_config" -q -f "$here/drop_schema.sql"
+psql "$psql_config" -q -f "$here/create_schema.sql"
+
+cargo run --release --bin chocolatier -- \
+  --psql_config "$psql_config" \
+  --log_file "$workdir/chocolatier.log" \
+  fetch \
+  --bigtable_emulator_host "$emulator_host" \
+  --bigtable_instance $instance \
+  --block_range "$block_range"
+
+psql "$psql_config" -At -F ' ' -c \
+  "SELECT slot, block_index, encode(signature, 'hex') FROM transactions ORDER BY slot, block_index" \
+  >"$workdir/actual_transactions.txt"
+
+expected=$blocks_dir/expected_transactions.txt
+if [[ $record == --record ]]; then
+  cp "$workdir/actual_transactions.txt" "$expected"
+  echo "recorded $(wc -l <"$expected") transactions to $expected"
+elif diff -u "$expected" "$workdir/actual_transactions.txt"; then
+  echo "ok: $(wc -l <"$expected") transactions"
+else
+  echo "transactions differ from $expected"
+  exit 1
+fi
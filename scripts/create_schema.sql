@@ -3,9 +3,34 @@ CREATE TABLE transactions (
   slot BIGINT NOT NULL,
   block_index BIGINT NOT NULL,
   signature BYTEA NOT NULL,
+  -- see chocolatier::storage for the encoding
   transaction BYTEA
 );
 
+CREATE INDEX transactions_by_position ON transactions (slot, block_index);
+
+-- fetch inserts with ON CONFLICT (signature) DO NOTHING so overlapping ranges don't duplicate.
+-- DBs from before this need scripts/remove_duplicates.sql first
+CREATE UNIQUE INDEX transactions_by_signature ON transactions (signature);
+
+-- inclusive slot ranges fetch has committed. rows for a range are in the same DB transaction as
+-- its checkpoint
+CREATE TABLE fetch_checkpoints (
+  start_slot BIGINT NOT NULL,
+  end_slot BIGINT NOT NULL,
+  completed_at TIMESTAMPTZ NOT NULL,
+  -- chocolatier::filter::FilterSpec the range was fetched with
+  filter JSONB NOT NULL,
+  -- slots in the range without a block. every other slot was a block
+  skipped BIGINT[] NOT NULL DEFAULT '{}',
+  -- blocks the ledger listed but didn't return. fetched again by the next run over the range
+  failed BIGINT[] NOT NULL DEFAULT '{}',
+  -- chocolatier::checkpoint::Stage, the table the range's rows went to
+  stage TEXT NOT NULL DEFAULT 'transactions'
+);
+
+CREATE INDEX fetch_checkpoints_by_range ON fetch_checkpoints (start_slot, end_slot);
+
 CREATE TABLE partition_failures (
   program_key BYTEA NOT NULL,
   slot BIGINT NOT NULL,
@@ -29,6 +54,47 @@ CREATE TABLE partitions (
 
 CREATE INDEX by_partition_key ON partitions (partition_key) ;
 
+-- re-partitioning a range deletes the derived rows for its transactions first
+CREATE INDEX partitions_by_position ON partitions (slot, block_index);
+
+CREATE INDEX partition_failures_by_position ON partition_failures (slot, block_index);
+
+-- one row per partition run over [start, end) in (slot, block_index). shards commit separately, so
+-- a run without finished_at failed or is still going and may have replaced only part of its range
+CREATE TABLE partition_runs (
+  id BIGSERIAL PRIMARY KEY,
+  started_at TIMESTAMPTZ NOT NULL,
+  finished_at TIMESTAMPTZ,
+  start_slot BIGINT NOT NULL,
+  start_block_index BIGINT NOT NULL,
+  end_slot BIGINT NOT NULL,
+  end_block_index BIGINT NOT NULL,
+  transactions BIGINT NOT NULL,
+  failed_transactions BIGINT NOT NULL
+);
+
+-- what a run did with each instruction, by program and variant. variant is NULL for programs
+-- without a partitioner and instructions that don't deserialize
+CREATE TABLE partition_stats (
+  run_id BIGINT NOT NULL REFERENCES partition_runs (id),
+  program_key BYTEA NOT NULL,
+  variant VARCHAR,
+  partitioned BIGINT NOT NULL,
+  -- no partitioner for the program
+  ignored BIGINT NOT NULL,
+  -- the partitioner returned None
+  returned_none BIGINT NOT NULL,
+  failed BIGINT NOT NULL
+);
+
+-- last (slot, block_index) partition has processed. a single row
+CREATE TABLE partition_watermark (
+  id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+  slot BIGINT NOT NULL,
+  block_index BIGINT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
 CREATE TYPE token_meta AS (
   account_index SMALLINT,
   mint_key BYTEA,
@@ -66,6 +132,7 @@ CREATE TABLE bonbons (
   metadata_key VARCHAR NOT NULL,
   mint_key VARCHAR NOT NULL,
   mint_authority VARCHAR,
+  freeze_authority VARCHAR,
   current_owner VARCHAR,
   current_account VARCHAR,
   edition_status edition_status NOT NULL,
@@ -78,11 +145,19 @@ CREATE TYPE creator AS (
   share SMALLINT
 );
 
+CREATE TYPE glazing_source AS enum (
+  'data',
+  'master_edition',
+  'creator_verification',
+  'collection_verification'
+);
+
 CREATE TABLE glazings (
   metadata_key VARCHAR NOT NULL,
   name VARCHAR,
   symbol VARCHAR,
   uri VARCHAR,
+  seller_fee_basis_points INTEGER,
   collection_key VARCHAR,
   collection_verified BOOLEAN,
   creator0 creator,
@@ -90,18 +165,175 @@ CREATE TABLE glazings (
   creator2 creator,
   creator3 creator,
   creator4 creator,
-  instruction_index instruction_index NOT NULL
+  instruction_index instruction_index NOT NULL,
+  source glazing_source NOT NULL
+);
+
+CREATE TYPE glazing_change_kind AS enum (
+  'name',
+  'symbol',
+  'uri',
+  'creator_added',
+  'creator_removed',
+  'creator_share_changed',
+  'creator_verified',
+  'collection_changed',
+  'collection_verified',
+  'royalty_changed'
+);
+
+CREATE TABLE glazing_changes (
+  metadata_key VARCHAR NOT NULL,
+  instruction_index instruction_index NOT NULL,
+  kind glazing_change_kind NOT NULL,
+  -- creator or collection the change is about
+  address VARCHAR,
+  old_value VARCHAR,
+  new_value VARCHAR
+);
+
+CREATE INDEX glazing_changes_by_kind ON glazing_changes (kind);
+
+CREATE TYPE transfer_kind AS enum (
+  'mint',
+  'transfer',
+  'transfer_checked',
+  'owner_change',
+  'burn',
+  'burn_nft'
 );
 
 CREATE TABLE transfers (
   mint_key VARCHAR NOT NULL,
-  slot BIGINT NOT NULL,
+  instruction_index instruction_index NOT NULL,
+  signature VARCHAR NOT NULL,
+  kind transfer_kind NOT NULL,
+  authority VARCHAR NOT NULL,
+  delegated BOOLEAN NOT NULL,
   start_owner VARCHAR,
   start_account VARCHAR,
   end_owner VARCHAR,
   end_account VARCHAR
 );
 
+CREATE TYPE authority_kind AS enum (
+  'mint',
+  'freeze'
+);
+
+CREATE TABLE authority_changes (
+  mint_key VARCHAR NOT NULL,
+  kind authority_kind NOT NULL,
+  -- NULL when revoked
+  new_authority VARCHAR,
+  instruction_index instruction_index NOT NULL
+);
+
+CREATE TYPE anomaly_kind AS enum (
+  'creator_shares_not_hundred',
+  'unlisted_creator_verification',
+  'transfer_after_burn',
+  'mint_authority_retained',
+  'multiple_mints'
+);
+
+CREATE TABLE anomalies (
+  mint_key VARCHAR NOT NULL,
+  kind anomaly_kind NOT NULL,
+  instruction_index instruction_index,
+  details VARCHAR
+);
+
+CREATE INDEX anomalies_by_kind ON anomalies (kind);
+
+-- off-chain metadata. keyed by content as well as uri since non-arweave/ipfs uris can change
+CREATE TABLE metadata_documents (
+  uri VARCHAR NOT NULL,
+  content_hash BYTEA NOT NULL,
+  -- NULL if the content wasn't JSON
+  document JSONB,
+  violations VARCHAR[],
+  fetched_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (uri, content_hash)
+);
+
+CREATE TABLE glazing_documents (
+  metadata_key VARCHAR NOT NULL,
+  instruction_index instruction_index NOT NULL,
+  uri VARCHAR NOT NULL,
+  -- NULL if the fetch failed
+  content_hash BYTEA,
+  error VARCHAR
+);
+
+CREATE UNIQUE INDEX glazing_documents_by_glazing ON glazing_documents (metadata_key, instruction_index);
+
+CREATE TYPE activity_kind AS enum (
+  'mint',
+  'receive',
+  'send',
+  'burn',
+  'delegate',
+  'revoke',
+  'metadata_update',
+  'creator_verify',
+  'creator_unverify'
+);
+
+CREATE TABLE activities (
+  wallet VARCHAR NOT NULL,
+  instruction_index instruction_index NOT NULL,
+  kind activity_kind NOT NULL,
+  mint_key VARCHAR NOT NULL,
+  counterparty VARCHAR,
+  signature VARCHAR NOT NULL
+);
+
+CREATE INDEX activities_by_wallet ON activities (wallet, instruction_index);
+
+-- reassemble replaces a bonbon's activities
+CREATE INDEX activities_by_mint ON activities (mint_key);
+
+CREATE INDEX transfers_by_end_owner ON transfers (end_owner);
+
+-- current owner of each bonbon. maintained by reassemble
+CREATE TABLE holdings (
+  mint_key VARCHAR PRIMARY KEY,
+  owner VARCHAR NOT NULL,
+  account VARCHAR NOT NULL,
+  collection_key VARCHAR,
+  collection_verified BOOLEAN,
+  since instruction_index NOT NULL
+);
+
+CREATE INDEX holdings_by_owner ON holdings (owner);
+
+-- latest collection claim of each bonbon. maintained by reassemble
+CREATE TABLE collection_members (
+  mint_key VARCHAR PRIMARY KEY,
+  collection_key VARCHAR NOT NULL,
+  verified BOOLEAN NOT NULL,
+  owner VARCHAR,
+  burned BOOLEAN NOT NULL,
+  mint_slot BIGINT
+);
+
+CREATE INDEX collection_members_by_collection ON collection_members (collection_key);
+
+-- recomputed from collection_members for collections touched by reassemble
+CREATE TABLE collections (
+  collection_key VARCHAR PRIMARY KEY,
+  verified_members BIGINT NOT NULL,
+  unverified_claimants BIGINT NOT NULL,
+  unique_holders BIGINT NOT NULL,
+  -- bonbons held -> number of holders
+  holder_distribution JSONB NOT NULL,
+  total_burns BIGINT NOT NULL,
+  first_mint_slot BIGINT,
+  last_mint_slot BIGINT,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
 CREATE FUNCTION numeric2bytea(_n NUMERIC) RETURNS BYTEA AS $$
 DECLARE
     _b BYTEA := '\x';
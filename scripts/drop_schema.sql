@@ -1,12 +1,31 @@
+DROP TABLE IF EXISTS collections;
+DROP TABLE IF EXISTS collection_members;
+DROP TABLE IF EXISTS activities;
+DROP TABLE IF EXISTS holdings;
+DROP TABLE IF EXISTS glazing_documents;
+DROP TABLE IF EXISTS metadata_documents;
+DROP TABLE IF EXISTS anomalies;
+DROP TABLE IF EXISTS authority_changes;
 DROP TABLE IF EXISTS transfers;
+DROP TABLE IF EXISTS glazing_changes;
 DROP TABLE IF EXISTS glazings;
 DROP TABLE IF EXISTS bonbons;
 
+DROP TABLE IF EXISTS partition_watermark;
+DROP TABLE IF EXISTS partition_stats;
+DROP TABLE IF EXISTS partition_runs;
 DROP TABLE IF EXISTS account_keys;
 DROP TABLE IF EXISTS partitions ;
 DROP TABLE IF EXISTS partition_failures ;
+DROP TABLE IF EXISTS fetch_checkpoints;
 DROP TABLE IF EXISTS transactions ;
 
+DROP TYPE IF EXISTS activity_kind;
+DROP TYPE IF EXISTS anomaly_kind;
+DROP TYPE IF EXISTS authority_kind;
+DROP TYPE IF EXISTS glazing_change_kind;
+DROP TYPE IF EXISTS glazing_source;
+DROP TYPE IF EXISTS transfer_kind;
 DROP TYPE IF EXISTS instruction_index;
 DROP TYPE IF EXISTS limited_edition;
 DROP TYPE IF EXISTS edition_status;
@@ -0,0 +1,89 @@
+#!/usr/bin/env python3
+# Minimal JSON-RPC server for running `chocolatier fetch --rpc` locally. Serves getBlocksWithLimit
+# and getBlock from a directory of getBlock dumps named by slot (the same layout as
+# `fetch --source json`). Every Nth request can be answered with a 429 to exercise retries.
+#
+#   python3 scripts/mock_rpc.py --blocks_dir ./blocks --port 8899 --rate_limit_every 5
+#   chocolatier fetch --rpc http://127.0.0.1:8899 --block_range 150000000-150000100
+
+import argparse
+import json
+import os
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+SLOT_SKIPPED = -32007
+
+
+def load_blocks(blocks_dir):
+    blocks = {}
+    for name in os.listdir(blocks_dir):
+        stem, _ = os.path.splitext(name)
+        if stem.isdigit():
+            blocks[int(stem)] = os.path.join(blocks_dir, name)
+    return blocks
+
+
+def make_handler(blocks, rate_limit_every):
+    slots = sorted(blocks)
+    counter = {"requests": 0}
+
+    class Handler(BaseHTTPRequestHandler):
+        def reply(self, status, body, headers=()):
+            content = json.dumps(body).encode()
+            self.send_response(status)
+            self.send_header("Content-Type", "application/json")
+            self.send_header("Content-Length", str(len(content)))
+            for key, value in headers:
+                self.send_header(key, value)
+            self.end_headers()
+            self.wfile.write(content)
+
+        def do_POST(self):
+            counter["requests"] += 1
+            if rate_limit_every and counter["requests"] % rate_limit_every == 0:
+                self.reply(429, {"error": "rate limited"}, [("Retry-After", "1")])
+                return
+
+            request = json.loads(self.rfile.read(int(self.headers["Content-Length"])))
+            method, params = request["method"], request.get("params", [])
+            response = {"jsonrpc": "2.0", "id": request.get("id")}
+
+            if method == "getBlocksWithLimit":
+                start, limit = params[0], params[1]
+                response["result"] = [s for s in slots if s >= start][:limit]
+            elif method == "getBlock" and params[0] in blocks:
+                with open(blocks[params[0]]) as f:
+                    block = json.load(f)
+                response["result"] = block.get("result", block)
+            elif method == "getBlock":
+                response["error"] = {
+                    "code": SLOT_SKIPPED,
+                    "message": "Slot {} was skipped".format(params[0]),
+                }
+            else:
+                response["error"] = {"code": -32601, "message": "Method not found"}
+
+            self.reply(200, response)
+
+        def log_message(self, format, *args):
+            pass
+
+    return Handler
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--blocks_dir", required=True)
+    parser.add_argument("--port", type=int, default=8899)
+    parser.add_argument("--rate_limit_every", type=int, default=0)
+    args = parser.parse_args()
+
+    blocks = load_blocks(args.blocks_dir)
+    server = ThreadingHTTPServer(
+        ("127.0.0.1", args.port), make_handler(blocks, args.rate_limit_every))
+    print("serving {} blocks on port {}".format(len(blocks), args.port))
+    server.serve_forever()
+
+
+if __name__ == "__main__":
+    main()
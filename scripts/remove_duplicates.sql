@@ -1,3 +1,6 @@
+-- one-off for DBs fetched before transactions had a unique signature. fetch now skips
+-- duplicates itself, so this only needs to run once before creating the index:
+--   CREATE UNIQUE INDEX transactions_by_signature ON transactions (signature);
 DELETE FROM transactions a USING (
     SELECT min(ctid) as ctid, signature
     FROM transactions